@@ -0,0 +1,58 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use hmac::{Hmac, Mac};
+use rolling_token_auth::{MockClock, RollingTokenManager, TokenBytes};
+use sha2::Sha256;
+
+const SECRET: &[u8] = b"benchmark_secret";
+
+/// The original implementation: keys a new HMAC for every token and compares hex strings.
+fn naive_token(timestamp: i64) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET).unwrap();
+    mac.update(timestamp.to_string().as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// A manager with a fixed clock, so tokens don't expire during a long run.
+fn manager() -> RollingTokenManager {
+    RollingTokenManager::new(SECRET, 30, None).with_clock(MockClock::from_unix_secs(1_740_000_000))
+}
+
+fn generate(c: &mut Criterion) {
+    let manager = manager();
+    let mut group = c.benchmark_group("generate");
+    group.bench_function("naive", |b| b.iter(|| naive_token(black_box(58_000_000))));
+    group.bench_function("token", |b| b.iter(|| manager.generate_token().unwrap()));
+    group.bench_function("token_bytes", |b| b.iter(|| manager.generate_token_bytes().unwrap()));
+    group.finish();
+}
+
+fn validate(c: &mut Criterion) {
+    let mut manager = manager();
+    let token = manager.generate_token().unwrap();
+    let bytes: TokenBytes = token.as_str().parse().unwrap();
+    let validator = manager.clone().into_shared();
+
+    let mut group = c.benchmark_group("validate");
+    group.bench_function("naive", |b| {
+        b.iter(|| {
+            let expected: Vec<String> = (-1..=1)
+                .map(|offset| naive_token(token.interval_index().unwrap() + offset))
+                .collect();
+            expected.iter().any(|expected| expected == black_box(token.as_str()))
+        })
+    });
+    group.bench_function("token", |b| b.iter(|| manager.validate(black_box(token.as_str())).unwrap()));
+    group.bench_function("token_bytes", |b| b.iter(|| manager.validate_bytes(black_box(&bytes)).unwrap()));
+    group.bench_function("shared_token_bytes", |b| {
+        b.iter(|| validator.validate_bytes(black_box(&bytes)).unwrap())
+    });
+    group.bench_function("unknown", |b| {
+        b.iter(|| manager.validate_bytes(black_box(&TokenBytes([0; 32]))).unwrap_err())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, generate, validate);
+criterion_main!(benches);
@@ -0,0 +1,222 @@
+use std::fmt;
+use std::str::FromStr;
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::encoding::Code;
+
+/// MAC algorithm used to sign intervals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// HMAC-SHA1, the default of most authenticator apps.
+    Sha1,
+    /// HMAC-SHA256.
+    #[default]
+    Sha256,
+    /// HMAC-SHA512.
+    Sha512,
+    /// HMAC-SHA3-256.
+    #[cfg(feature = "sha3")]
+    Sha3_256,
+    /// Keyed BLAKE3, with the key derived from the secret using BLAKE3's key derivation mode.
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl Algorithm {
+    /// Length of the MAC in bytes.
+    pub fn output_len(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+            #[cfg(feature = "sha3")]
+            Self::Sha3_256 => 32,
+            #[cfg(feature = "blake3")]
+            Self::Blake3 => 32,
+        }
+    }
+
+    /// Name as used in `otpauth://` URIs, e.g. `SHA256`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA256",
+            Self::Sha512 => "SHA512",
+            #[cfg(feature = "sha3")]
+            Self::Sha3_256 => "SHA3-256",
+            #[cfg(feature = "blake3")]
+            Self::Blake3 => "BLAKE3",
+        }
+    }
+
+    /// Processes the key once, so signing a message only needs to clone the resulting state.
+    pub(crate) fn keyed(self, key: &[u8]) -> KeyedMac {
+        match self {
+            Self::Sha1 => KeyedMac::Sha1(new_hmac(key)),
+            Self::Sha256 => KeyedMac::Sha256(new_hmac(key)),
+            Self::Sha512 => KeyedMac::Sha512(new_hmac(key)),
+            #[cfg(feature = "sha3")]
+            Self::Sha3_256 => KeyedMac::Sha3_256(new_hmac(key)),
+            #[cfg(feature = "blake3")]
+            Self::Blake3 => {
+                let key = blake3::derive_key("rolling-token-auth keyed BLAKE3 MAC", key);
+                KeyedMac::Blake3(blake3::Hasher::new_keyed(&key))
+            }
+        }
+    }
+
+    fn all() -> &'static [Self] {
+        &[
+            Self::Sha1,
+            Self::Sha256,
+            Self::Sha512,
+            #[cfg(feature = "sha3")]
+            Self::Sha3_256,
+            #[cfg(feature = "blake3")]
+            Self::Blake3,
+        ]
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses names as returned by [`Algorithm::name`], ignoring case.
+impl FromStr for Algorithm {
+    type Err = UnknownAlgorithm;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+            .ok_or(UnknownAlgorithm)
+    }
+}
+
+/// Serializes as the [name](Algorithm::name).
+#[cfg(feature = "serde")]
+impl serde::Serialize for Algorithm {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// Deserializes from a name, case-insensitively like [`Algorithm::from_str`].
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Algorithm {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse()
+            .map_err(|_| serde::de::Error::custom(format!("unknown algorithm {name:?}")))
+    }
+}
+
+/// The name passed to [`Algorithm::from_str`] is not a supported (or enabled) algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownAlgorithm;
+
+impl fmt::Display for UnknownAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown MAC algorithm")
+    }
+}
+
+impl std::error::Error for UnknownAlgorithm {}
+
+fn new_hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8]) -> M {
+    <M as Mac>::new_from_slice(key).expect("HMAC can take key of any size")
+}
+
+/// MAC state with the key already processed. Clone it, feed the message with [`update`](Self::update) and
+/// [`finalize`](Self::finalize) the clone.
+// Boxing the larger states would allocate on every clone
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+pub(crate) enum KeyedMac {
+    Sha1(Hmac<Sha1>),
+    Sha256(Hmac<Sha256>),
+    Sha512(Hmac<Sha512>),
+    #[cfg(feature = "sha3")]
+    Sha3_256(Hmac<sha3::Sha3_256>),
+    #[cfg(feature = "blake3")]
+    Blake3(blake3::Hasher),
+}
+
+impl KeyedMac {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha1(mac) => mac.update(data),
+            Self::Sha256(mac) => mac.update(data),
+            Self::Sha512(mac) => mac.update(data),
+            #[cfg(feature = "sha3")]
+            Self::Sha3_256(mac) => mac.update(data),
+            #[cfg(feature = "blake3")]
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub(crate) fn finalize(self) -> Code {
+        match self {
+            Self::Sha1(mac) => Code::new(&mac.finalize().into_bytes()),
+            Self::Sha256(mac) => Code::new(&mac.finalize().into_bytes()),
+            Self::Sha512(mac) => Code::new(&mac.finalize().into_bytes()),
+            #[cfg(feature = "sha3")]
+            Self::Sha3_256(mac) => Code::new(&mac.finalize().into_bytes()),
+            #[cfg(feature = "blake3")]
+            Self::Blake3(hasher) => Code::new(hasher.finalize().as_bytes()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(algorithm: Algorithm, key: &[u8], parts: &[&[u8]]) -> Code {
+        let mut mac = algorithm.keyed(key);
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize()
+    }
+
+    #[test]
+    fn test_names_roundtrip() {
+        for &algorithm in Algorithm::all() {
+            assert_eq!(algorithm.name().to_lowercase().parse(), Ok(algorithm));
+            assert_eq!(sign(algorithm, b"secret", &[b"100"]).as_bytes().len(), algorithm.output_len());
+        }
+        assert_eq!("MD5".parse::<Algorithm>(), Err(UnknownAlgorithm));
+    }
+
+    #[test]
+    fn test_algorithms_differ() {
+        let sha256 = sign(Algorithm::Sha256, b"secret", &[b"100"]);
+        let sha512 = sign(Algorithm::Sha512, b"secret", &[b"100"]);
+        assert_ne!(sha256.as_bytes(), &sha512.as_bytes()[..32]);
+    }
+
+    #[test]
+    fn test_keyed_state_is_reusable() {
+        for &algorithm in Algorithm::all() {
+            let keyed = algorithm.keyed(b"secret");
+            let mut first = keyed.clone();
+            first.update(b"1");
+            first.update(b"00");
+            assert_eq!(first.finalize().as_bytes(), sign(algorithm, b"secret", &[b"100"]).as_bytes());
+
+            let mut second = keyed.clone();
+            second.update(b"101");
+            assert_ne!(second.finalize().as_bytes(), sign(algorithm, b"secret", &[b"100"]).as_bytes());
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::replay::ReplayCache;
+use crate::secret::KeyedMacs;
+use crate::{Algorithm, Clock, ConfigError, Encoding, Keyring, RollingTokenManager, SystemClock, TOTP_DIGITS};
+
+/// Largest accepted tolerance, which bounds the number of tokens kept in the window.
+pub const MAX_TOLERANCE: i64 = 1024;
+
+/// Validating builder for [`RollingTokenManager`], created with [`RollingTokenManager::builder`] or
+/// [`RollingTokenManager::keyring_builder`].
+#[derive(Clone)]
+pub struct RollingTokenManagerBuilder {
+    keyring: Keyring,
+    algorithm: Algorithm,
+    encoding: Encoding,
+    accept_legacy_hex: bool,
+    one_time_use: bool,
+    interval: Option<Duration>,
+    past_tolerance: i64,
+    future_tolerance: i64,
+    epoch: SystemTime,
+    clock: Arc<dyn Clock>,
+}
+
+impl RollingTokenManagerBuilder {
+    pub(crate) fn new(keyring: Keyring) -> Self {
+        Self {
+            keyring,
+            algorithm: Algorithm::default(),
+            encoding: Encoding::default(),
+            accept_legacy_hex: false,
+            one_time_use: false,
+            interval: None,
+            past_tolerance: 1,
+            future_tolerance: 1,
+            epoch: UNIX_EPOCH,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replaces the keys, e.g. when a [`ReloadingValidator`](crate::ReloadingValidator) loads new ones.
+    pub(crate) fn keyring(mut self, keyring: Keyring) -> Self {
+        self.keyring = keyring;
+        self
+    }
+
+    /// Sets the MAC algorithm (defaults to [`Algorithm::Sha256`]).
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Sets the token format (defaults to [`Encoding::Hex`]).
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// With [`Encoding::Versioned`], also accepts bare hex tokens of [`Encoding::Hex`] (defaults to `false`), e.g.
+    /// while clients migrate to the versioned format. Has no effect with other encodings.
+    pub fn accept_legacy_hex(mut self, accept: bool) -> Self {
+        self.accept_legacy_hex = accept;
+        self
+    }
+
+    /// Accepts every token only once (defaults to `false`). Later presentations fail with
+    /// [`ValidationError::Replayed`](crate::ValidationError::Replayed).
+    ///
+    /// Accepted tokens are remembered until their interval leaves the tolerance window, so memory grows with the
+    /// number of distinct tokens accepted per window. Clones of the manager share the record, so a token accepted by
+    /// one clone is rejected by the others. Include a nonce in the [`TokenContext`](crate::TokenContext)
+    /// to allow several one-time tokens per interval.
+    pub fn one_time_use(mut self, enabled: bool) -> Self {
+        self.one_time_use = enabled;
+        self
+    }
+
+    /// Sets how long a token is valid, with millisecond resolution. Either this or [`interval_secs`](Self::interval_secs)
+    /// is required.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Sets how long a token is valid in seconds.
+    pub fn interval_secs(self, interval: i64) -> Self {
+        // Non-positive values map to zero, which `build` rejects
+        self.interval(u64::try_from(interval).map_or(Duration::ZERO, Duration::from_secs))
+    }
+
+    /// Sets how many intervals before and after the current one are accepted (defaults to 1).
+    pub fn tolerance(self, tolerance: i64) -> Self {
+        self.past_tolerance(tolerance).future_tolerance(tolerance)
+    }
+
+    /// Sets how many intervals before the current one are accepted (defaults to 1).
+    pub fn past_tolerance(mut self, tolerance: i64) -> Self {
+        self.past_tolerance = tolerance;
+        self
+    }
+
+    /// Sets how many intervals after the current one are accepted (defaults to 1).
+    pub fn future_tolerance(mut self, tolerance: i64) -> Self {
+        self.future_tolerance = tolerance;
+        self
+    }
+
+    /// Sets the time intervals are counted from (defaults to the Unix epoch), like T0 in RFC 6238.
+    ///
+    /// Intervals start at `epoch + n * interval`, so e.g. `UNIX_EPOCH + Duration::from_secs(15 * 60)` with a one-hour
+    /// interval rolls over at a quarter past every hour. `n` is negative before the epoch, so an epoch in the future
+    /// works too. Generation and verification must use the same epoch.
+    pub fn epoch(mut self, epoch: SystemTime) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Sets the time source (defaults to [`SystemClock`]).
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn build(self) -> Result<RollingTokenManager, ConfigError> {
+        self.keyring.check()?;
+        let interval = self.interval.ok_or(ConfigError::MissingInterval)?;
+        if interval.is_zero() || interval.subsec_nanos() % 1_000_000 != 0 || interval.as_millis() > i64::MAX as u128 {
+            return Err(ConfigError::InvalidInterval);
+        }
+        for tolerance in [self.past_tolerance, self.future_tolerance] {
+            if !(0..=MAX_TOLERANCE).contains(&tolerance) {
+                return Err(ConfigError::InvalidTolerance);
+            }
+        }
+
+        if let Encoding::Totp { digits } = self.encoding {
+            if !TOTP_DIGITS.contains(&digits) {
+                return Err(ConfigError::InvalidDigits);
+            }
+        }
+
+        Ok(RollingTokenManager {
+            keyed_macs: KeyedMacs::new(
+                self.keyring
+                    .keys()
+                    .iter()
+                    .map(|key| self.algorithm.keyed(key.secret.expose()))
+                    .collect(),
+            ),
+            keyring: self.keyring,
+            algorithm: self.algorithm,
+            encoding: self.encoding,
+            accept_legacy_hex: self.accept_legacy_hex,
+            interval_ms: interval.as_millis() as i64,
+            past_tolerance: self.past_tolerance,
+            future_tolerance: self.future_tolerance,
+            epoch: self.epoch,
+            active_tokens: Vec::new(),
+            replay_cache: self.one_time_use.then(|| Arc::new(Mutex::new(ReplayCache::default()))),
+            clock: self.clock,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_invalid_parameters() {
+        let build = |secret: &str, interval: i64, tolerance: i64| {
+            RollingTokenManager::builder(secret)
+                .interval_secs(interval)
+                .tolerance(tolerance)
+                .build()
+                .err()
+        };
+
+        assert_eq!(build("secret", 30, 1), None);
+        assert_eq!(build("", 30, 1), Some(ConfigError::EmptySecret));
+        assert_eq!(build("secret", 0, 1), Some(ConfigError::InvalidInterval));
+        assert_eq!(build("secret", -30, 1), Some(ConfigError::InvalidInterval));
+        assert_eq!(build("secret", 30, -1), Some(ConfigError::InvalidTolerance));
+        assert_eq!(build("secret", 30, MAX_TOLERANCE + 1), Some(ConfigError::InvalidTolerance));
+        assert_eq!(
+            RollingTokenManager::builder("secret")
+                .interval_secs(30)
+                .future_tolerance(-1)
+                .build()
+                .err(),
+            Some(ConfigError::InvalidTolerance)
+        );
+        assert_eq!(
+            RollingTokenManager::builder("secret").build().err(),
+            Some(ConfigError::MissingInterval)
+        );
+        assert_eq!(
+            RollingTokenManager::builder("secret")
+                .interval(Duration::from_micros(1500))
+                .build()
+                .err(),
+            Some(ConfigError::InvalidInterval)
+        );
+        assert_eq!(
+            RollingTokenManager::builder("secret")
+                .interval_secs(30)
+                .encoding(Encoding::Totp { digits: 5 })
+                .build()
+                .err(),
+            Some(ConfigError::InvalidDigits)
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Algorithm, ConfigError, Encoding, RollingTokenManager, RollingTokenManagerBuilder, SecretSource};
+
+/// Serializable manager settings, e.g. loaded from a TOML, JSON or YAML file with the matching serde crate:
+///
+/// ```toml
+/// secret = { env = "TOKEN_SECRET", encoding = "base64" }
+/// interval_secs = 3600
+/// past_tolerance = 2
+/// algorithm = "SHA512"
+/// ```
+///
+/// The secret is a [`SecretSource`], so it can stay out of the config file. Exactly one of `interval_secs` and
+/// `interval_ms` must be set. `tolerance` sets both tolerances, and `past_tolerance`/`future_tolerance` override it;
+/// all default to 1. The other fields default like the builder's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RollingTokenConfig {
+    pub secret: SecretSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub past_tolerance: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub future_tolerance: Option<i64>,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub encoding: Encoding,
+    #[serde(default)]
+    pub accept_legacy_hex: bool,
+    #[serde(default)]
+    pub one_time_use: bool,
+}
+
+impl RollingTokenConfig {
+    /// Loads the secret and returns a builder with these settings, e.g. to set a clock before building.
+    pub fn builder(&self) -> Result<RollingTokenManagerBuilder, ConfigError> {
+        let interval = match (self.interval_secs, self.interval_ms) {
+            (Some(secs), None) => Duration::from_secs(secs),
+            (None, Some(ms)) => Duration::from_millis(ms),
+            (None, None) => return Err(ConfigError::MissingInterval),
+            (Some(_), Some(_)) => return Err(ConfigError::ConflictingIntervals),
+        };
+        let tolerance = self.tolerance.unwrap_or(1);
+
+        Ok(RollingTokenManager::builder(self.secret.load()?)
+            .interval(interval)
+            .past_tolerance(self.past_tolerance.unwrap_or(tolerance))
+            .future_tolerance(self.future_tolerance.unwrap_or(tolerance))
+            .algorithm(self.algorithm)
+            .encoding(self.encoding)
+            .accept_legacy_hex(self.accept_legacy_hex)
+            .one_time_use(self.one_time_use))
+    }
+
+    /// Loads the secret and builds a manager with these settings.
+    pub fn build(&self) -> Result<RollingTokenManager, ConfigError> {
+        self.builder()?.build()
+    }
+}
+
+impl TryFrom<&RollingTokenConfig> for RollingTokenManager {
+    type Error = ConfigError;
+
+    fn try_from(config: &RollingTokenConfig) -> Result<Self, Self::Error> {
+        config.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::{MockClock, SecretError};
+
+    #[test]
+    fn test_load_toml_and_json() {
+        let path = TempPath::file("config", "746573745f736563726574\n");
+        let toml = format!(
+            r#"
+            secret = {{ file = {path:?}, encoding = "hex" }}
+            interval_secs = 30
+            tolerance = 2
+            future_tolerance = 0
+            algorithm = "sha512"
+            encoding = {{ totp = {{ digits = 8 }} }}
+            "#,
+            path = &*path
+        );
+        let config: RollingTokenConfig = toml::from_str(&toml).unwrap();
+        assert_eq!(config.algorithm, Algorithm::Sha512);
+        assert_eq!(config.encoding, Encoding::Totp { digits: 8 });
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<RollingTokenConfig>(&json).unwrap(), config);
+
+        let clock = MockClock::from_unix_secs(3000);
+        let mut manager = config.builder().unwrap().clock(clock.clone()).build().unwrap();
+        let expected = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .algorithm(Algorithm::Sha512)
+            .encoding(Encoding::Totp { digits: 8 })
+            .clock(clock)
+            .build()
+            .unwrap();
+        let old = expected.generate_token_with_offset(-2).unwrap();
+        assert!(manager.is_valid(old.as_str()));
+        assert!(!manager.is_valid(expected.generate_token_with_offset(1).unwrap().as_str()));
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        let parse = |json: &str| serde_json::from_str::<RollingTokenConfig>(json);
+        assert!(parse(r#"{"secret": {"env": "X"}, "interval_secs": 30, "algorithm": "MD5"}"#).is_err());
+        assert!(parse(r#"{"secret": {"env": "X"}, "interval_secs": 30, "unknown": 1}"#).is_err());
+
+        let missing = parse(r#"{"secret": {"env": "ROLLING_TOKEN_AUTH_UNSET"}, "interval_secs": 30}"#).unwrap();
+        assert_eq!(
+            missing.build().err(),
+            Some(ConfigError::Secret(SecretError::MissingEnv("ROLLING_TOKEN_AUTH_UNSET".to_string())))
+        );
+
+        let path = TempPath::file("invalid", "test_secret");
+        let mut config = parse(&format!(r#"{{"secret": {{"file": {:?}}}}}"#, &*path)).unwrap();
+        assert_eq!(config.build().err(), Some(ConfigError::MissingInterval));
+        config.interval_secs = Some(30);
+        config.interval_ms = Some(500);
+        assert_eq!(config.build().err(), Some(ConfigError::ConflictingIntervals));
+        config.interval_secs = None;
+        assert!(RollingTokenManager::try_from(&config).is_ok());
+    }
+}
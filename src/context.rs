@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::algorithm::KeyedMac;
+
+/// Caller-supplied data a token is bound to, so it can't be replayed in a different context.
+///
+/// Fields are MACed together with the interval in a length-prefixed encoding sorted by name, so the order in which
+/// they are set doesn't matter and no two different contexts produce the same MAC input. An empty context produces
+/// the same tokens as [`RollingTokenManager::generate_token`](crate::RollingTokenManager::generate_token).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenContext {
+    fields: BTreeMap<Cow<'static, str>, Vec<u8>>,
+}
+
+impl TokenContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds the token to the service it is meant for.
+    pub fn audience(self, audience: impl AsRef<[u8]>) -> Self {
+        self.with_field("audience", audience)
+    }
+
+    /// Binds the token to a user or device ID.
+    pub fn subject(self, subject: impl AsRef<[u8]>) -> Self {
+        self.with_field("subject", subject)
+    }
+
+    /// Binds the token to an HTTP method and path.
+    pub fn request(self, method: impl AsRef<[u8]>, path: impl AsRef<[u8]>) -> Self {
+        self.with_field("method", method).with_field("path", path)
+    }
+
+    /// Binds the token to the fingerprint of the client's TLS certificate.
+    pub fn client_cert_fingerprint(self, fingerprint: impl AsRef<[u8]>) -> Self {
+        self.with_field("client_cert", fingerprint)
+    }
+
+    /// Binds the token to a custom field. Setting a field again replaces its value.
+    pub fn with_field(mut self, name: impl Into<Cow<'static, str>>, value: impl AsRef<[u8]>) -> Self {
+        self.fields.insert(name.into(), value.as_ref().to_vec());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Feeds `len(name) || name || len(value) || value` for every field to `mac`, with 4-byte big-endian lengths.
+    pub(crate) fn update_mac(&self, mac: &mut KeyedMac) {
+        for (name, value) in &self.fields {
+            for part in [name.as_bytes(), value] {
+                mac.update(&(part.len() as u32).to_be_bytes());
+                mac.update(part);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockClock, RollingTokenManager, ValidationError};
+
+    fn manager() -> RollingTokenManager {
+        RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .clock(MockClock::from_unix_secs(3000))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bound_token() {
+        let mut manager = manager();
+        let context = TokenContext::new().audience("service-a").request("POST", "/webhooks");
+        let token = manager.generate_token_with_context(&context).unwrap();
+
+        let reordered = TokenContext::new().request("POST", "/webhooks").audience("service-a");
+        assert!(manager.is_valid_with_context(token.as_str(), &reordered));
+
+        let other_service = TokenContext::new().audience("service-b").request("POST", "/webhooks");
+        assert_eq!(
+            manager.validate_with_context(token.as_str(), &other_service),
+            Err(ValidationError::Unknown)
+        );
+        assert!(!manager.is_valid(token.as_str()));
+
+        let old = manager.generate_token_with_context_and_offset(&context, -2).unwrap();
+        assert_eq!(
+            manager.validate_with_context(old.as_str(), &context),
+            Err(ValidationError::Expired { offset: -2 })
+        );
+    }
+
+    #[test]
+    fn test_unambiguous_encoding() {
+        let manager = manager();
+        let a = TokenContext::new().with_field("a", "bc");
+        let b = TokenContext::new().with_field("ab", "c");
+        assert_ne!(
+            manager.generate_token_with_context(&a).unwrap().as_str(),
+            manager.generate_token_with_context(&b).unwrap().as_str()
+        );
+        assert_eq!(
+            manager.generate_token_with_context(&TokenContext::new()).unwrap().as_str(),
+            manager.generate_token().unwrap().as_str()
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::{Arc, Mutex};
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::replay::ReplayCache;
+use crate::secret::{self, KeyedMacs, Secret};
+use crate::{Key, Keyring, RollingTokenManager};
+
+/// Prefixes of the HKDF info, so secrets derived for different uses of the master secret never collide.
+const PURPOSE_INFO: &[u8] = b"rolling-token-auth purpose\0";
+const SUBJECT_INFO: &[u8] = b"rolling-token-auth subject\0";
+
+/// Length of derived secrets, the output size of SHA-256.
+const DERIVED_LEN: usize = 32;
+
+impl RollingTokenManager {
+    /// Derives a manager for one purpose, e.g. `"webhooks"`, whose tokens are not valid for other purposes or for
+    /// this manager.
+    ///
+    /// Each key's secret is replaced by HKDF-SHA256 of it with `purpose` as the info, keeping its ID, state and
+    /// validity, so only the master secret has to be distributed. The other settings are copied, except that the
+    /// derived manager has its own replay cache. Derivation is deterministic, so every instance with the same master
+    /// secret derives the same manager.
+    pub fn derive(&self, purpose: &str) -> Self {
+        self.derived(PURPOSE_INFO, purpose)
+    }
+
+    /// Derives the manager of one user or device, see [`SubjectValidator`](crate::SubjectValidator).
+    ///
+    /// Like [`derive`](Self::derive), but in a separate namespace, so a subject ID never derives the same keys as a
+    /// purpose of the same name.
+    pub fn for_subject(&self, subject_id: &str) -> Self {
+        self.derived(SUBJECT_INFO, subject_id)
+    }
+
+    fn derived(&self, info_prefix: &[u8], label: &str) -> Self {
+        let keyring = self.keyring.derive(info_prefix, label);
+        Self {
+            keyed_macs: KeyedMacs::new(keyring.keys().iter().map(|key| self.algorithm.keyed(key.secret.expose())).collect()),
+            keyring,
+            active_tokens: Vec::new(),
+            replay_cache: self.replay_cache.as_ref().map(|_| Arc::new(Mutex::new(ReplayCache::default()))),
+            ..self.clone()
+        }
+    }
+}
+
+impl Keyring {
+    /// Derives every key for `label`, see [`RollingTokenManager::derive`].
+    fn derive(&self, info_prefix: &[u8], label: &str) -> Keyring {
+        self.keys().iter().fold(Keyring::new(), |keyring, key| {
+            keyring.with(Key {
+                secret: derive_secret(key.secret.expose(), info_prefix, label),
+                ..key.clone()
+            })
+        })
+    }
+}
+
+fn derive_secret(master: &[u8], info_prefix: &[u8], label: &str) -> Secret {
+    let mut info = [info_prefix, label.as_bytes()].concat();
+    let mut derived = vec![0; DERIVED_LEN];
+    Hkdf::<Sha256>::new(None, master)
+        .expand(&info, &mut derived)
+        .expect("output length is valid for HKDF-SHA256");
+    secret::wipe(&mut info);
+    Secret::new(derived)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Encoding, KeyState, MockClock, ValidationError};
+
+    #[test]
+    fn test_purposes_are_separated() {
+        let clock = MockClock::from_unix_secs(3000);
+        let master = RollingTokenManager::builder("master_secret")
+            .interval_secs(30)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut webhooks = master.derive("webhooks");
+        let mut sessions = master.derive("sessions");
+
+        let token = webhooks.generate_token().unwrap();
+        assert!(webhooks.is_valid(token.as_str()));
+        assert_eq!(sessions.validate(token.as_str()), Err(ValidationError::Unknown));
+        assert!(!master.clone().is_valid(token.as_str()));
+        assert!(!webhooks.is_valid(master.generate_token().unwrap().as_str()));
+
+        // Another instance with the same master secret derives the same keys
+        let other = RollingTokenManager::builder("master_secret")
+            .interval_secs(30)
+            .clock(clock)
+            .build()
+            .unwrap()
+            .derive("webhooks");
+        assert_eq!(other.generate_token().unwrap(), token);
+    }
+
+    #[test]
+    fn test_derive_keeps_keys_and_settings() {
+        let keyring = Keyring::new()
+            .with_key("old", "old_secret", KeyState::VerifyOnly)
+            .with_key("new", "new_secret", KeyState::Primary);
+        let clock = MockClock::from_unix_secs(3000);
+        let old = RollingTokenManager::keyring_builder(Keyring::new().with_key("old", "old_secret", KeyState::Primary))
+            .interval_secs(30)
+            .encoding(Encoding::Versioned)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let master = RollingTokenManager::keyring_builder(keyring)
+            .interval_secs(30)
+            .encoding(Encoding::Versioned)
+            .one_time_use(true)
+            .clock(clock)
+            .build()
+            .unwrap();
+
+        let mut derived = master.derive("webhooks");
+        let token = old.derive("webhooks").generate_token().unwrap();
+        assert_eq!(derived.validate(token.as_str()).unwrap().key_id.as_ref(), "old");
+        assert_eq!(derived.validate(token.as_str()), Err(ValidationError::Replayed));
+
+        let token = derived.generate_token().unwrap();
+        assert!(token.as_str().starts_with("v1.new."));
+        assert!(master.clone().validate(token.as_str()).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use std::ops::RangeInclusive;
+
+use subtle::{Choice, ConstantTimeEq};
+
+use crate::algorithm::KeyedMac;
+use crate::{Algorithm, TokenContext};
+
+/// Supported number of digits for [`Encoding::Totp`].
+pub const TOTP_DIGITS: RangeInclusive<u8> = 6..=10;
+
+const MAX_CODE_LEN: usize = 64;
+
+/// Prefix of tokens in the [`Encoding::Versioned`] format.
+pub(crate) const VERSION_PREFIX: &str = "v1.";
+
+/// How an interval counter is signed and turned into a token string.
+///
+/// With the `serde` feature, encodings are written as `"hex"`, `"versioned"` or `{ "totp": { "digits": 6 } }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum Encoding {
+    /// The MAC over the decimal counter, hex-encoded. This is the crate's original format.
+    #[default]
+    Hex,
+    /// RFC 6238 TOTP: the MAC over the 8-byte big-endian counter, dynamically truncated to `digits` decimal digits.
+    ///
+    /// Together with [`Algorithm::Sha1`], a 30 second interval and the Unix epoch, codes match authenticator apps.
+    Totp { digits: u8 },
+    /// Self-describing `v1.<key-id>.<counter>.<mac>` tokens with a hex-encoded MAC over the version, key ID, counter
+    /// and context.
+    ///
+    /// The verifier reads the key and interval from the token, so it rejects out-of-window tokens without computing
+    /// a MAC and otherwise computes exactly one. Bare hex tokens of [`Encoding::Hex`] are only accepted if enabled
+    /// with [`RollingTokenManagerBuilder::accept_legacy_hex`](crate::RollingTokenManagerBuilder::accept_legacy_hex).
+    Versioned,
+}
+
+impl Encoding {
+    /// Computes the bytes a token for `counter` and `context` is compared by, without allocating.
+    pub(crate) fn sign(self, mac: &KeyedMac, key_id: &str, counter: i64, context: &TokenContext) -> Code {
+        let mut mac = mac.clone();
+        match self {
+            Self::Hex => mac.update(format_decimal(counter, &mut [0; 20])),
+            Self::Totp { .. } => mac.update(&counter.to_be_bytes()),
+            Self::Versioned => {
+                mac.update(b"v1");
+                mac.update(&(key_id.len() as u32).to_be_bytes());
+                mac.update(key_id.as_bytes());
+                mac.update(&counter.to_be_bytes());
+            }
+        }
+        // No counter encoding can be confused with a length prefix: decimal digits never contain a zero byte,
+        // and the other counters have a fixed length
+        context.update_mac(&mut mac);
+
+        let mac = mac.finalize();
+        match self {
+            Self::Hex | Self::Versioned => mac,
+            Self::Totp { digits } => truncate(&mac, digits),
+        }
+    }
+
+    pub(crate) fn render(self, key_id: &str, counter: i64, code: &Code) -> String {
+        match self {
+            Self::Hex => hex::encode(code.as_bytes()),
+            Self::Totp { .. } => code.as_bytes().iter().map(|&digit| digit as char).collect(),
+            Self::Versioned => format!("{VERSION_PREFIX}{key_id}.{counter}.{}", hex::encode(code.as_bytes())),
+        }
+    }
+
+    /// Parses a presented token into comparable bytes, or `None` if it is malformed.
+    ///
+    /// Versioned tokens are parsed with [`VersionedToken::parse`] instead; this only handles their bare MAC.
+    pub(crate) fn parse(self, algorithm: Algorithm, token: &str) -> Option<Code> {
+        let mut code = Code::EMPTY;
+        match self {
+            Self::Hex | Self::Versioned => {
+                code.len = algorithm.output_len();
+                hex::decode_to_slice(token, &mut code.bytes[..code.len]).ok()?;
+            }
+            Self::Totp { digits } => {
+                if token.len() != digits as usize || !token.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                code.len = token.len();
+                code.bytes[..code.len].copy_from_slice(token.as_bytes());
+            }
+        }
+        Some(code)
+    }
+}
+
+/// Formats `value` like `value.to_string()` into `buf`, which fits every `i64`.
+fn format_decimal(value: i64, buf: &mut [u8; 20]) -> &[u8] {
+    let mut start = buf.len();
+    let mut rest = value.unsigned_abs();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    if value < 0 {
+        start -= 1;
+        buf[start] = b'-';
+    }
+    &buf[start..]
+}
+
+/// The parts of a `v1.<key-id>.<counter>.<mac>` token. The key ID may itself contain dots.
+///
+/// Only the canonical form rendered by [`Encoding::render`] is accepted: a counter without leading zeros, `+` or
+/// `-0`, and a MAC in lowercase hex. Every token then has exactly one spelling, so the format can evolve without
+/// ambiguity.
+pub(crate) struct VersionedToken<'a> {
+    pub(crate) key_id: &'a str,
+    pub(crate) counter: i64,
+    pub(crate) mac: Code,
+}
+
+impl<'a> VersionedToken<'a> {
+    pub(crate) fn parse(algorithm: Algorithm, token: &'a str) -> Option<Self> {
+        let rest = token.strip_prefix(VERSION_PREFIX)?;
+        let mut parts = rest.rsplitn(3, '.');
+        let mac = parts.next()?;
+        if mac.bytes().any(|b| b.is_ascii_uppercase()) {
+            return None;
+        }
+        let mac = Encoding::Hex.parse(algorithm, mac)?;
+        let digits = parts.next()?;
+        let counter = digits.parse().ok()?;
+        if format_decimal(counter, &mut [0; 20]) != digits.as_bytes() {
+            return None;
+        }
+        let key_id = parts.next()?;
+        Some(Self { key_id, counter, mac })
+    }
+}
+
+/// RFC 4226 dynamic truncation of `mac` to `digits` ASCII digits.
+fn truncate(mac: &Code, digits: u8) -> Code {
+    let mac = mac.as_bytes();
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(mac[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+
+    let mut value = binary as u64 % 10u64.pow(digits as u32);
+    let mut code = Code::EMPTY;
+    code.len = digits as usize;
+    for digit in code.bytes[..code.len].iter_mut().rev() {
+        *digit = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+    code
+}
+
+/// Fixed-size buffer holding the bytes a token is compared by: the raw MAC or the ASCII digits of a TOTP code.
+///
+/// `Eq` and `Hash` are only for the replay cache of already accepted tokens; compare against expected codes with
+/// [`ConstantTimeEq`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Code {
+    bytes: [u8; MAX_CODE_LEN],
+    len: usize,
+}
+
+impl Code {
+    /// Never equal to a real code, used in place of malformed input.
+    pub(crate) const EMPTY: Self = Self {
+        bytes: [0; MAX_CODE_LEN],
+        len: 0,
+    };
+
+    pub(crate) fn new(bytes: &[u8]) -> Self {
+        let mut code = Self::EMPTY;
+        code.len = bytes.len();
+        code.bytes[..code.len].copy_from_slice(bytes);
+        code
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn zeroize(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.bytes);
+        self.len = 0;
+    }
+}
+
+impl ConstantTimeEq for Code {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        // Unused bytes are always zero, so comparing the whole buffer is equivalent and independent of the length
+        self.bytes.ct_eq(&other.bytes) & self.len.ct_eq(&other.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockClock, RollingTokenManager};
+
+    fn totp(secret: &str, algorithm: Algorithm, unix_secs: u64) -> String {
+        RollingTokenManager::builder(secret)
+            .interval_secs(30)
+            .algorithm(algorithm)
+            .encoding(Encoding::Totp { digits: 8 })
+            .clock(MockClock::from_unix_secs(unix_secs))
+            .build()
+            .unwrap()
+            .generate_token()
+            .unwrap()
+            .into_string()
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        let sha1 = "12345678901234567890";
+        let sha256 = "12345678901234567890123456789012";
+        let sha512 = "1234567890123456789012345678901234567890123456789012345678901234";
+
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, expected_sha1, expected_sha256, expected_sha512) in vectors {
+            assert_eq!(totp(sha1, Algorithm::Sha1, time), expected_sha1);
+            assert_eq!(totp(sha256, Algorithm::Sha256, time), expected_sha256);
+            assert_eq!(totp(sha512, Algorithm::Sha512, time), expected_sha512);
+        }
+    }
+
+    #[test]
+    fn test_rfc4226_vectors() {
+        let manager = RollingTokenManager::builder("12345678901234567890")
+            .interval_secs(30)
+            .algorithm(Algorithm::Sha1)
+            .encoding(Encoding::Totp { digits: 6 })
+            .clock(MockClock::from_unix_secs(0))
+            .build()
+            .unwrap();
+
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871", "520489",
+        ];
+        for (counter, expected) in expected.into_iter().enumerate() {
+            assert_eq!(manager.generate_token_with_offset(counter as i64).unwrap().as_str(), expected);
+        }
+    }
+
+    #[test]
+    fn test_format_decimal() {
+        for value in [0, 7, 10, 100, -1, -100, 1_234_567_890, i64::MAX, i64::MIN] {
+            assert_eq!(format_decimal(value, &mut [0; 20]), value.to_string().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_versioned_counter_is_canonical() {
+        let mac = "00".repeat(32);
+        let parse = |counter: &str| VersionedToken::parse(Algorithm::Sha256, &format!("v1.key.{counter}.{mac}")).map(|t| t.counter);
+        assert_eq!(parse("100"), Some(100));
+        assert_eq!(parse("0"), Some(0));
+        assert_eq!(parse("-7"), Some(-7));
+        for counter in ["00100", "-0", "+100", "-07", "", "1e3"] {
+            assert_eq!(parse(counter), None, "{counter}");
+        }
+    }
+
+    #[test]
+    fn test_totp_validation() {
+        let mut manager = RollingTokenManager::builder("12345678901234567890")
+            .interval_secs(30)
+            .encoding(Encoding::Totp { digits: 6 })
+            .clock(MockClock::from_unix_secs(3000))
+            .build()
+            .unwrap();
+
+        let token = manager.generate_token_with_offset(-1).unwrap();
+        assert!(manager.is_valid(token.as_str()));
+        assert_eq!(manager.validate("12345"), Err(crate::ValidationError::Malformed));
+        assert_eq!(manager.validate("12345a"), Err(crate::ValidationError::Malformed));
+    }
+}
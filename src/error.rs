@@ -0,0 +1,163 @@
+use std::fmt;
+
+use crate::SecretError;
+
+/// Reason a token was rejected by [`RollingTokenManager::validate`](crate::RollingTokenManager::validate).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The token does not have the configured encoding or length.
+    Malformed,
+    /// The token belongs to an interval `offset` intervals before the tolerance window allows.
+    Expired { offset: i64 },
+    /// The token belongs to an interval `offset` intervals after the tolerance window allows.
+    NotYetValid { offset: i64 },
+    /// The token does not match any nearby interval, e.g. because it was minted with a different secret.
+    Unknown,
+    /// The token is valid but was already accepted once, and the manager is in one-time-use mode.
+    Replayed,
+    /// The current interval could not be determined.
+    Clock(ClockError),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "token is malformed"),
+            Self::Expired { offset } => write!(f, "token expired ({offset} intervals from now)"),
+            Self::NotYetValid { offset } => write!(f, "token is not yet valid ({offset} intervals from now)"),
+            Self::Unknown => write!(f, "token does not match any known interval"),
+            Self::Replayed => write!(f, "token was already used"),
+            Self::Clock(err) => write!(f, "cannot validate token: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Clock(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ClockError> for ValidationError {
+    fn from(err: ClockError) -> Self {
+        Self::Clock(err)
+    }
+}
+
+/// Invalid parameters passed to [`RollingTokenManagerBuilder`](crate::RollingTokenManagerBuilder).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// A secret is empty.
+    EmptySecret,
+    /// Two keys in the keyring share this ID.
+    DuplicateKeyId(String),
+    /// The keyring has no primary key.
+    NoPrimaryKey,
+    /// This primary key has the same `not_before` as another primary key, so neither takes precedence.
+    AmbiguousPrimaryKey(String),
+    /// This key's `not_before` is not before its `not_after`.
+    InvalidKeySchedule(String),
+    /// No interval was configured.
+    MissingInterval,
+    /// A [`RollingTokenConfig`](crate::RollingTokenConfig) sets both `interval_secs` and `interval_ms`.
+    ConflictingIntervals,
+    /// The interval is not a positive whole number of milliseconds.
+    InvalidInterval,
+    /// The tolerance is negative or larger than [`MAX_TOLERANCE`](crate::MAX_TOLERANCE).
+    InvalidTolerance,
+    /// The number of TOTP digits is outside [`TOTP_DIGITS`](crate::TOTP_DIGITS).
+    InvalidDigits,
+    /// The secret of a [`RollingTokenConfig`](crate::RollingTokenConfig) could not be loaded.
+    Secret(SecretError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptySecret => write!(f, "secret must not be empty"),
+            Self::DuplicateKeyId(id) => write!(f, "duplicate key ID {id:?}"),
+            Self::NoPrimaryKey => write!(f, "keyring must have a primary key"),
+            Self::AmbiguousPrimaryKey(id) => write!(f, "primary key {id:?} has the same not_before as another primary key"),
+            Self::InvalidKeySchedule(id) => write!(f, "key {id:?} has not_before at or after not_after"),
+            Self::MissingInterval => write!(f, "interval must be set"),
+            Self::ConflictingIntervals => write!(f, "only one of interval_secs and interval_ms may be set"),
+            Self::InvalidInterval => write!(f, "interval must be a positive whole number of milliseconds"),
+            Self::InvalidTolerance => write!(f, "tolerance must be between 0 and {}", crate::MAX_TOLERANCE),
+            Self::InvalidDigits => {
+                let digits = crate::TOTP_DIGITS;
+                write!(f, "TOTP digits must be between {} and {}", digits.start(), digits.end())
+            }
+            Self::Secret(err) => write!(f, "cannot load secret: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Secret(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<SecretError> for ConfigError {
+    fn from(err: SecretError) -> Self {
+        Self::Secret(err)
+    }
+}
+
+/// The clock reported a time so far from the configured epoch that its interval, or the intervals around it that
+/// validation looks at, don't fit in an `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockError;
+
+impl fmt::Display for ClockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "clock is too far from the epoch")
+    }
+}
+
+impl std::error::Error for ClockError {}
+
+/// A token could not be generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerateError {
+    /// The current interval could not be determined.
+    Clock(ClockError),
+    /// No primary key in the keyring is live at the current time.
+    NoSigningKey,
+    /// [`TokenBytes`](crate::TokenBytes) were requested from a manager that doesn't produce 32-byte hex tokens.
+    UnsupportedTokenBytes,
+    /// The offset moves the interval out of the range of an `i64`.
+    InvalidOffset,
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Clock(err) => write!(f, "cannot generate token: {err}"),
+            Self::NoSigningKey => write!(f, "no primary key is live"),
+            Self::UnsupportedTokenBytes => write!(f, "token bytes require the hex encoding and a 32-byte MAC"),
+            Self::InvalidOffset => write!(f, "offset is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Clock(err) => Some(err),
+            Self::NoSigningKey | Self::UnsupportedTokenBytes | Self::InvalidOffset => None,
+        }
+    }
+}
+
+impl From<ClockError> for GenerateError {
+    fn from(err: ClockError) -> Self {
+        Self::Clock(err)
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::secret::Secret;
+use crate::ConfigError;
+
+/// ID of the key created by [`RollingTokenManager::new`](crate::RollingTokenManager::new) and
+/// [`RollingTokenManager::builder`](crate::RollingTokenManager::builder).
+pub const DEFAULT_KEY_ID: &str = "default";
+
+/// What a key in a [`Keyring`] is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    /// Signs new tokens and verifies presented ones.
+    ///
+    /// If several primary keys are live at once, the one with the latest `not_before` signs, so a rotation can be
+    /// pre-staged by adding the new key as primary with a future `not_before`.
+    Primary,
+    /// Only verifies presented tokens, e.g. a retired key during a rotation.
+    VerifyOnly,
+}
+
+/// A secret with an ID, reported in [`ValidatedToken::key_id`](crate::ValidatedToken::key_id) when it matches.
+///
+/// A key is live from `not_before` (inclusive) until `not_after` (exclusive), evaluated against the manager's clock.
+/// Outside that range it neither signs nor verifies tokens.
+#[derive(Clone)]
+pub struct Key {
+    pub(crate) id: Arc<str>,
+    pub(crate) secret: Secret,
+    pub(crate) state: KeyState,
+    pub(crate) not_before: Option<SystemTime>,
+    pub(crate) not_after: Option<SystemTime>,
+}
+
+impl Key {
+    pub fn new(id: impl Into<String>, secret: impl Into<Vec<u8>>, state: KeyState) -> Self {
+        Self {
+            id: id.into().into(),
+            secret: Secret::new(secret.into()),
+            state,
+            not_before: None,
+            not_after: None,
+        }
+    }
+
+    /// Sets when the key becomes live.
+    pub fn not_before(mut self, time: SystemTime) -> Self {
+        self.not_before = Some(time);
+        self
+    }
+
+    /// Sets when the key stops being live.
+    pub fn not_after(mut self, time: SystemTime) -> Self {
+        self.not_after = Some(time);
+        self
+    }
+
+    pub(crate) fn is_live(&self, now: SystemTime) -> bool {
+        self.not_before.is_none_or(|not_before| not_before <= now) && self.not_after.is_none_or(|not_after| now < not_after)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn state(&self) -> KeyState {
+        self.state
+    }
+}
+
+/// Redacts the secret.
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("id", &self.id)
+            .field("state", &self.state)
+            .field("not_before", &self.not_before)
+            .field("not_after", &self.not_after)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A set of keys for rotating secrets without a flag day.
+///
+/// A rotation typically adds the new key as [`KeyState::VerifyOnly`] everywhere, then promotes it to
+/// [`KeyState::Primary`] while demoting the old key, and finally removes the old key once its tokens have expired.
+/// With [`Key::not_before`] and [`Key::not_after`] the same rotation can be scheduled ahead of time in one deploy.
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    keys: Vec<Key>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn single(secret: Vec<u8>) -> Self {
+        Self::new().with_key(DEFAULT_KEY_ID, secret, KeyState::Primary)
+    }
+
+    pub fn with_key(self, id: impl Into<String>, secret: impl Into<Vec<u8>>, state: KeyState) -> Self {
+        self.with(Key::new(id, secret, state))
+    }
+
+    pub fn with(mut self, key: Key) -> Self {
+        self.push(key);
+        self
+    }
+
+    pub fn push(&mut self, key: Key) {
+        self.keys.push(key);
+    }
+
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// Index of the key used to sign new tokens at `now`: the live primary key with the latest `not_before`.
+    pub(crate) fn signing_key(&self, now: SystemTime) -> Option<usize> {
+        self.keys
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| key.state == KeyState::Primary && key.is_live(now))
+            .max_by_key(|(_, key)| key.not_before)
+            .map(|(index, _)| index)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), ConfigError> {
+        let primaries: Vec<&Key> = self.keys.iter().filter(|key| key.state == KeyState::Primary).collect();
+        if primaries.is_empty() {
+            return Err(ConfigError::NoPrimaryKey);
+        }
+
+        for (i, key) in self.keys.iter().enumerate() {
+            if key.secret.expose().is_empty() {
+                return Err(ConfigError::EmptySecret);
+            }
+            if self.keys[..i].iter().any(|other| other.id == key.id) {
+                return Err(ConfigError::DuplicateKeyId(key.id.to_string()));
+            }
+            if let (Some(not_before), Some(not_after)) = (key.not_before, key.not_after) {
+                if not_before >= not_after {
+                    return Err(ConfigError::InvalidKeySchedule(key.id.to_string()));
+                }
+            }
+        }
+
+        // Otherwise the signing key would depend on the order of the keyring
+        for (i, key) in primaries.iter().enumerate() {
+            if primaries[..i].iter().any(|other| other.not_before == key.not_before) {
+                return Err(ConfigError::AmbiguousPrimaryKey(key.id.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GenerateError, MockClock, RollingTokenManager, ValidationError};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn manager(keyring: Keyring) -> RollingTokenManager {
+        RollingTokenManager::keyring_builder(keyring)
+            .interval_secs(30)
+            .clock(MockClock::from_unix_secs(3000))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rotation() {
+        let old = manager(Keyring::single(b"old_secret".to_vec()));
+        let new = manager(Keyring::new().with_key("new", "new_secret", KeyState::Primary));
+        let mut rotating = manager(Keyring::new().with_key("old", "old_secret", KeyState::VerifyOnly).with_key(
+            "new",
+            "new_secret",
+            KeyState::Primary,
+        ));
+
+        let old_token = old.generate_token().unwrap();
+        assert_eq!(&*rotating.validate(old_token.as_str()).unwrap().key_id, "old");
+
+        let new_token = new.generate_token_with_offset(-1).unwrap();
+        let validated = rotating.validate(new_token.as_str()).unwrap();
+        assert_eq!((&*validated.key_id, validated.offset), ("new", -1));
+
+        // Signs with the primary key only
+        assert_eq!(rotating.generate_token().unwrap().as_str(), new.generate_token().unwrap().as_str());
+
+        let mut old_only = manager(Keyring::single(b"old_secret".to_vec()));
+        assert_eq!(old_only.validate(new_token.as_str()), Err(ValidationError::Unknown));
+    }
+
+    #[test]
+    fn test_invalid_keyrings() {
+        let build = |keyring: Keyring| RollingTokenManager::keyring_builder(keyring).interval_secs(30).build().err();
+
+        assert_eq!(build(Keyring::new()), Some(ConfigError::NoPrimaryKey));
+        assert_eq!(
+            build(Keyring::new().with_key("a", "a", KeyState::VerifyOnly)),
+            Some(ConfigError::NoPrimaryKey)
+        );
+        assert_eq!(
+            build(
+                Keyring::new()
+                    .with_key("a", "a", KeyState::Primary)
+                    .with_key("b", "b", KeyState::Primary)
+            ),
+            Some(ConfigError::AmbiguousPrimaryKey("b".to_string()))
+        );
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(
+            build(Keyring::new().with(Key::new("a", "a", KeyState::Primary).not_before(at(10)).not_after(at(10)))),
+            Some(ConfigError::InvalidKeySchedule("a".to_string()))
+        );
+        assert_eq!(
+            build(
+                Keyring::new()
+                    .with_key("a", "a", KeyState::Primary)
+                    .with_key("a", "b", KeyState::VerifyOnly)
+            ),
+            Some(ConfigError::DuplicateKeyId("a".to_string()))
+        );
+        assert_eq!(
+            build(Keyring::new().with_key("a", "", KeyState::Primary)),
+            Some(ConfigError::EmptySecret)
+        );
+    }
+
+    #[test]
+    fn test_scheduled_rotation() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let clock = MockClock::from_unix_secs(3000);
+        let keyring = Keyring::new()
+            .with(Key::new("a", "secret_a", KeyState::Primary).not_after(at(6000)))
+            .with(Key::new("b", "secret_b", KeyState::Primary).not_before(at(4500)))
+            .with(
+                Key::new("c", "secret_c", KeyState::Primary)
+                    .not_before(at(9000))
+                    .not_after(at(9300)),
+            );
+        let mut manager = RollingTokenManager::keyring_builder(keyring)
+            .interval_secs(30)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        let token_a = manager.generate_token().unwrap();
+        assert_eq!(&*manager.validate(token_a.as_str()).unwrap().key_id, "a");
+
+        clock.set(at(4500)); // b takes over signing, a is still accepted
+        let token_b = manager.generate_token().unwrap();
+        assert_eq!(&*manager.validate(token_b.as_str()).unwrap().key_id, "b");
+
+        clock.set(at(5990));
+        let late_a = RollingTokenManager::new("secret_a", 30, None).with_clock(clock.clone());
+        let late_a = late_a.generate_token().unwrap();
+        assert_eq!(&*manager.validate(late_a.as_str()).unwrap().key_id, "a");
+
+        clock.set(at(6000)); // a is retired
+        assert_eq!(manager.validate(late_a.as_str()), Err(ValidationError::Unknown));
+
+        clock.set(at(9000)); // c only signs within its schedule
+        let token_c = manager.generate_token().unwrap();
+        assert_eq!(&*manager.validate(token_c.as_str()).unwrap().key_id, "c");
+        clock.set(at(9300));
+        assert_ne!(manager.generate_token().unwrap().as_str(), token_c.as_str());
+    }
+
+    #[test]
+    fn test_no_live_signing_key() {
+        let keyring = Keyring::new().with(Key::new("a", "secret_a", KeyState::Primary).not_before(UNIX_EPOCH + Duration::from_secs(60)));
+        let manager = RollingTokenManager::keyring_builder(keyring)
+            .interval_secs(30)
+            .clock(MockClock::from_unix_secs(30))
+            .build()
+            .unwrap();
+        assert_eq!(manager.generate_token().err(), Some(GenerateError::NoSigningKey));
+    }
+}
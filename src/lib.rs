@@ -1,116 +1,714 @@
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
 
-type HmacSha256 = Hmac<Sha256>;
+mod algorithm;
+mod builder;
+mod clock;
+#[cfg(feature = "serde")]
+mod config;
+mod context;
+mod derive;
+mod encoding;
+mod error;
+mod keyring;
+mod otpauth;
+#[cfg(feature = "passphrase")]
+mod passphrase;
+mod reload;
+mod replay;
+mod secret;
+mod shared;
+mod source;
+mod subject;
+#[cfg(test)]
+mod testutil;
+mod token;
+
+pub use algorithm::{Algorithm, UnknownAlgorithm};
+pub use builder::{RollingTokenManagerBuilder, MAX_TOLERANCE};
+pub use clock::{Clock, MockClock, SystemClock};
+#[cfg(feature = "serde")]
+pub use config::RollingTokenConfig;
+pub use context::TokenContext;
+pub use encoding::{Encoding, TOTP_DIGITS};
+pub use error::{ClockError, ConfigError, GenerateError, ValidationError};
+pub use keyring::{Key, KeyState, Keyring, DEFAULT_KEY_ID};
+pub use otpauth::{OtpAuthError, OtpAuthUri};
+#[cfg(feature = "passphrase")]
+pub use passphrase::{PassphraseError, PassphraseKdf, PassphraseParams, MIN_SALT_LEN};
+pub use reload::{KeySource, ReloadingValidator};
+pub use shared::SharedValidator;
+pub use source::{SecretEncoding, SecretError, SecretSource};
+pub use subject::SubjectValidator;
+pub use token::{Token, TokenBytes, TOKEN_BYTES_LEN};
+
+use encoding::{Code, VersionedToken, VERSION_PREFIX};
+use replay::ReplayCache;
+use secret::KeyedMacs;
+use token::Validity;
 
-#[derive(Debug, Clone)]
-pub struct Token {
-    pub token: String,
+/// How many intervals beyond the tolerance window are checked to tell expired tokens from unknown ones.
+const EXPIRY_LOOKAROUND: i64 = 4;
+
+/// A token accepted by [`RollingTokenManager::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedToken {
+    /// The interval the token was generated for.
     pub timestamp: i64,
+    /// The interval relative to the current one, e.g. `-1` for a token from the previous interval.
+    pub offset: i64,
+    /// ID of the key the token was signed with.
+    pub key_id: Arc<str>,
 }
 
-impl Token {
-    fn get_offset(&self, manager: &RollingTokenManager) -> i64 {
-        self.timestamp - manager.current_timestamp()
+/// Expected code of a key and interval within the tolerance window, kept for constant-time comparison.
+#[derive(Clone)]
+struct ActiveToken {
+    key: usize,
+    timestamp: i64,
+    code: Code,
+}
+
+/// Wipes the code when it leaves the window.
+#[cfg(feature = "zeroize")]
+impl Drop for ActiveToken {
+    fn drop(&mut self) {
+        self.code.zeroize();
     }
 }
 
+/// A presented token, parsed according to the manager's encoding.
+enum Presented<'a> {
+    Versioned(&'a str),
+    /// `None` if the token is malformed.
+    Code(Option<Code>),
+}
+
 #[derive(Clone)]
 pub struct RollingTokenManager {
-    secret: Vec<u8>,
-    interval: i64,
-    tolerance: i64,
-    active_tokens: Vec<Token>,
+    keyring: Keyring,
+    /// MAC state per key of the keyring, so each secret is only processed once.
+    keyed_macs: KeyedMacs,
+    algorithm: Algorithm,
+    encoding: Encoding,
+    accept_legacy_hex: bool,
+    interval_ms: i64,
+    past_tolerance: i64,
+    future_tolerance: i64,
+    epoch: SystemTime,
+    active_tokens: Vec<ActiveToken>,
+    /// Shared between clones, so a token accepted by one clone is rejected by the others.
+    replay_cache: Option<Arc<Mutex<ReplayCache>>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl RollingTokenManager {
+    /// Creates a manager with an `interval` in seconds and an optional `tolerance` (defaults to 1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the secret is empty, the interval is not positive or the tolerance is out of range.
+    /// Use [`RollingTokenManager::builder`] to handle invalid parameters.
     pub fn new(secret: impl Into<Vec<u8>>, interval: i64, tolerance: Option<i64>) -> Self {
-        Self {
-            secret: secret.into(),
-            interval,
-            tolerance: tolerance.unwrap_or(1),
-            active_tokens: Vec::new(),
+        Self::builder(secret)
+            .interval_secs(interval)
+            .tolerance(tolerance.unwrap_or(1))
+            .build()
+            .expect("invalid RollingTokenManager parameters")
+    }
+
+    /// Creates a builder for a manager with a single secret, identified as [`DEFAULT_KEY_ID`].
+    pub fn builder(secret: impl Into<Vec<u8>>) -> RollingTokenManagerBuilder {
+        RollingTokenManagerBuilder::new(Keyring::single(secret.into()))
+    }
+
+    /// Creates a builder for a manager that signs with the keyring's primary key and accepts tokens from all live keys.
+    pub fn keyring_builder(keyring: Keyring) -> RollingTokenManagerBuilder {
+        RollingTokenManagerBuilder::new(keyring)
+    }
+
+    /// Replaces the time source, e.g. with a [`MockClock`] for tests.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self.active_tokens.clear();
+        self
+    }
+
+    /// Index of the interval containing `now`, negative before the epoch.
+    ///
+    /// Fails unless every interval validation may look at, up to the tolerance plus the expiry lookaround on either
+    /// side, fits in an `i64`, so the validation loops can't overflow.
+    fn timestamp_at(&self, now: SystemTime) -> Result<i64, ClockError> {
+        let interval_ms = self.interval_ms as i128;
+        let ms = match now.duration_since(self.epoch) {
+            Ok(elapsed) => elapsed.as_millis() as i128,
+            Err(err) => -(err.duration().as_nanos().div_ceil(1_000_000) as i128),
+        };
+        // Equals `secs / interval_secs` for second-aligned intervals, so their counters stay unchanged
+        let timestamp = i64::try_from(ms.div_euclid(interval_ms)).map_err(|_| ClockError)?;
+        timestamp.checked_sub(self.past_tolerance + EXPIRY_LOOKAROUND).ok_or(ClockError)?;
+        timestamp.checked_add(self.future_tolerance + EXPIRY_LOOKAROUND).ok_or(ClockError)?;
+        Ok(timestamp)
+    }
+
+    /// Index of the interval `offset` intervals from the one containing `now`.
+    fn offset_timestamp(&self, now: SystemTime, offset: i64) -> Result<i64, GenerateError> {
+        self.timestamp_at(now)?.checked_add(offset).ok_or(GenerateError::InvalidOffset)
+    }
+
+    /// Start of the interval `timestamp`, or `None` if it can't be represented.
+    fn interval_start(&self, timestamp: i64) -> Option<SystemTime> {
+        let ms = i128::from(timestamp) * i128::from(self.interval_ms);
+        let since_epoch = Duration::from_millis(u64::try_from(ms.unsigned_abs()).ok()?);
+        if ms < 0 {
+            self.epoch.checked_sub(since_epoch)
+        } else {
+            self.epoch.checked_add(since_epoch)
         }
     }
 
-    fn current_timestamp(&self) -> i64 {
-        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 / self.interval
+    /// When a token for `timestamp` is accepted: from the interval `future_tolerance` intervals before it until the
+    /// end of the interval `past_tolerance` intervals after it.
+    fn validity(&self, timestamp: i64) -> Option<Validity> {
+        Some(Validity {
+            valid_from: self.interval_start(timestamp.checked_sub(self.future_tolerance)?)?,
+            expires_at: self.interval_start(timestamp.checked_add(self.past_tolerance + 1)?)?,
+            clock: self.clock.clone(),
+        })
     }
 
-    pub fn generate_token_with_offset(&self, offset: i64) -> Token {
-        let timestamp = self.current_timestamp() + offset;
-        let encoded_timestamp = timestamp.to_string();
+    /// Encoding of the codes presented tokens are compared by: the bare MAC of legacy tokens for versioned managers.
+    fn code_encoding(&self) -> Encoding {
+        match self.encoding {
+            Encoding::Versioned => Encoding::Hex,
+            encoding => encoding,
+        }
+    }
 
-        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC can take key of any size");
+    fn sign(&self, key: usize, timestamp: i64, context: &TokenContext) -> Code {
+        self.code_encoding()
+            .sign(&self.keyed_macs[key], &self.keyring.keys()[key].id, timestamp, context)
+    }
 
-        mac.update(encoded_timestamp.as_bytes());
-        let result = mac.finalize();
-        let token = hex::encode(result.into_bytes());
+    fn signing_key(&self, now: SystemTime) -> Result<usize, GenerateError> {
+        self.keyring.signing_key(now).ok_or(GenerateError::NoSigningKey)
+    }
 
-        Token { token, timestamp }
+    pub fn generate_token_with_offset(&self, offset: i64) -> Result<Token, GenerateError> {
+        self.generate_token_with_context_and_offset(&TokenContext::new(), offset)
     }
 
-    pub fn generate_token(&self) -> Token {
+    pub fn generate_token(&self) -> Result<Token, GenerateError> {
         self.generate_token_with_offset(0)
     }
 
-    fn refresh_tokens(&mut self) {
-        let current_time = self.current_timestamp();
+    /// Generates a token that is only valid when validated with the same `context`.
+    pub fn generate_token_with_context(&self, context: &TokenContext) -> Result<Token, GenerateError> {
+        self.generate_token_with_context_and_offset(context, 0)
+    }
+
+    pub fn generate_token_with_context_and_offset(&self, context: &TokenContext, offset: i64) -> Result<Token, GenerateError> {
+        let now = self.clock.now();
+        let timestamp = self.offset_timestamp(now, offset)?;
+        let key = self.signing_key(now)?;
+        let key_id = &self.keyring.keys()[key].id;
+        let code = self.encoding.sign(&self.keyed_macs[key], key_id, timestamp, context);
+        let token = self.encoding.render(key_id, timestamp, &code);
+
+        Ok(Token::generated(token, timestamp, self.validity(timestamp)))
+    }
 
-        // Remove tokens outside tolerance
-        self.active_tokens
-            .retain(|token| (token.timestamp - current_time).abs() <= self.tolerance);
+    /// Whether the manager produces and accepts [`TokenBytes`].
+    fn supports_token_bytes(&self) -> bool {
+        self.encoding == Encoding::Hex && self.algorithm.output_len() == TOKEN_BYTES_LEN
+    }
+
+    /// Like [`generate_token_with_offset`](Self::generate_token_with_offset), but returns the token as raw bytes
+    /// without allocating.
+    ///
+    /// Fails with [`GenerateError::UnsupportedTokenBytes`] unless the manager uses [`Encoding::Hex`] and a 32-byte MAC.
+    pub fn generate_token_bytes_with_offset(&self, offset: i64) -> Result<TokenBytes, GenerateError> {
+        if !self.supports_token_bytes() {
+            return Err(GenerateError::UnsupportedTokenBytes);
+        }
+        let now = self.clock.now();
+        let timestamp = self.offset_timestamp(now, offset)?;
+        let code = self.sign(self.signing_key(now)?, timestamp, &TokenContext::new());
 
-        if self.active_tokens.len() as i64 == 1 + 2 * self.tolerance {
+        let mut bytes = [0; TOKEN_BYTES_LEN];
+        bytes.copy_from_slice(code.as_bytes());
+        Ok(TokenBytes(bytes))
+    }
+
+    pub fn generate_token_bytes(&self) -> Result<TokenBytes, GenerateError> {
+        self.generate_token_bytes_with_offset(0)
+    }
+
+    /// Brings `active_tokens` up to date for `current_time`, reusing the codes that are still in the window.
+    fn refresh_tokens(&self, active_tokens: &mut Vec<ActiveToken>, now: SystemTime, current_time: i64) {
+        let window = -self.past_tolerance..=self.future_tolerance;
+        let keys = self.keyring.keys();
+
+        // Remove tokens outside tolerance or of keys that are no longer live
+        active_tokens.retain(|token| window.contains(&(token.timestamp - current_time)) && keys[token.key].is_live(now));
+
+        let window_len = 1 + self.past_tolerance + self.future_tolerance;
+        let live_keys = keys.iter().filter(|key| key.is_live(now)).count() as i64;
+        if active_tokens.len() as i64 == window_len * live_keys {
             return;
         }
 
-        // Create a set of timestamps we need to generate
-        let mut needed_timestamps: Vec<i64> = (-self.tolerance..=self.tolerance).map(|offset| current_time + offset).collect();
+        for (index, _) in keys.iter().enumerate().filter(|(_, key)| key.is_live(now)) {
+            // Generate the tokens we don't have yet
+            for timestamp in window.clone().map(|offset| current_time + offset) {
+                if active_tokens.iter().any(|token| token.key == index && token.timestamp == timestamp) {
+                    continue;
+                }
+                let code = self.sign(index, timestamp, &TokenContext::new());
+                active_tokens.push(ActiveToken {
+                    key: index,
+                    timestamp,
+                    code,
+                });
+            }
+        }
+    }
+
+    /// Checks `token` against every key's tokens in the tolerance window.
+    ///
+    /// The comparison runs in constant time with respect to the expected codes: every active token is compared,
+    /// and malformed input is compared against an empty code instead of returning early.
+    ///
+    /// Rejected tokens up to a few intervals outside the window are reported as [`ValidationError::Expired`] or
+    /// [`ValidationError::NotYetValid`]; anything else is [`ValidationError::Unknown`].
+    pub fn validate(&mut self, token: &str) -> Result<ValidatedToken, ValidationError> {
+        self.validate_with_context(token, &TokenContext::new())
+    }
+
+    /// Like [`validate`](Self::validate), but only accepts tokens generated for the same `context`.
+    ///
+    /// Unlike plain tokens, the expected codes can't be cached across calls, so every call computes one MAC per
+    /// live key and interval in the window.
+    pub fn validate_with_context(&mut self, token: &str, context: &TokenContext) -> Result<ValidatedToken, ValidationError> {
+        self.validate_presented(self.present(token), context)
+    }
+
+    /// Like [`validate`](Self::validate) for [`TokenBytes`]. Once the expected codes of the current interval are
+    /// cached, this doesn't allocate.
+    ///
+    /// Token bytes are rejected as [`ValidationError::Malformed`] unless the manager uses [`Encoding::Hex`] and a
+    /// 32-byte MAC.
+    pub fn validate_bytes(&mut self, token: &TokenBytes) -> Result<ValidatedToken, ValidationError> {
+        self.validate_presented(self.present_bytes(token), &TokenContext::new())
+    }
+
+    fn present<'a>(&self, token: &'a str) -> Presented<'a> {
+        if self.is_versioned(token) {
+            Presented::Versioned(token)
+        } else {
+            Presented::Code(self.code_encoding().parse(self.algorithm, token))
+        }
+    }
+
+    fn present_bytes(&self, token: &TokenBytes) -> Presented<'static> {
+        Presented::Code(self.supports_token_bytes().then(|| Code::new(token.as_bytes())))
+    }
 
-        // Remove timestamps we already have
-        for token in &self.active_tokens {
-            needed_timestamps.retain(|&t| t != token.timestamp);
+    fn validate_presented(&mut self, presented: Presented<'_>, context: &TokenContext) -> Result<ValidatedToken, ValidationError> {
+        let (validated, code) = self.check_presented(presented, context)?;
+        if let Some(replay_cache) = &self.replay_cache {
+            replay_cache.lock().unwrap().check(&validated, code, self.past_tolerance)?;
         }
 
-        // Generate missing tokens
-        for timestamp in needed_timestamps {
-            let offset = timestamp - current_time;
-            let token = self.generate_token_with_offset(offset);
-            self.active_tokens.push(token);
+        Ok(validated)
+    }
+
+    /// Validates a presented token without consulting the replay cache, returning the matched code for it.
+    fn check_presented(&mut self, presented: Presented<'_>, context: &TokenContext) -> Result<(ValidatedToken, Code), ValidationError> {
+        let now = self.clock.now();
+        let current_time = self.timestamp_at(now)?;
+
+        match presented {
+            Presented::Versioned(token) => self.validate_versioned(token, context, now, current_time),
+            Presented::Code(parsed) => {
+                let mut active_tokens = mem::take(&mut self.active_tokens);
+                self.refresh_tokens(&mut active_tokens, now, current_time);
+                let result = self.validate_code(&active_tokens, parsed, context, now, current_time);
+                self.active_tokens = active_tokens;
+                result
+            }
         }
     }
 
+    /// Whether `token` is validated as a versioned token rather than by comparing codes.
+    fn is_versioned(&self, token: &str) -> bool {
+        self.encoding == Encoding::Versioned && (token.starts_with(VERSION_PREFIX) || !self.accept_legacy_hex)
+    }
+
+    /// Compares the `parsed` code against the expected code of every live key and interval in the window.
+    fn validate_code(
+        &self,
+        active_tokens: &[ActiveToken],
+        parsed: Option<Code>,
+        context: &TokenContext,
+        now: SystemTime,
+        current_time: i64,
+    ) -> Result<(ValidatedToken, Code), ValidationError> {
+        let well_formed = Choice::from(parsed.is_some() as u8);
+        let presented = parsed.unwrap_or(Code::EMPTY);
+
+        let mut matched = Choice::from(0);
+        let mut timestamp = 0;
+        let mut key = 0u64;
+        let mut compare = |candidate_key: usize, candidate_timestamp: i64, code: &Code| {
+            let eq = code.ct_eq(&presented);
+            timestamp.conditional_assign(&candidate_timestamp, eq);
+            key.conditional_assign(&(candidate_key as u64), eq);
+            matched |= eq;
+        };
+
+        if context.is_empty() {
+            for active in active_tokens {
+                compare(active.key, active.timestamp, &active.code);
+            }
+        } else {
+            for (index, _) in self.keyring.keys().iter().enumerate().filter(|(_, key)| key.is_live(now)) {
+                for offset in -self.past_tolerance..=self.future_tolerance {
+                    let candidate_timestamp = current_time + offset;
+                    compare(index, candidate_timestamp, &self.sign(index, candidate_timestamp, context));
+                }
+            }
+        }
+
+        if !bool::from(well_formed) {
+            return Err(ValidationError::Malformed);
+        }
+        if bool::from(matched) {
+            let validated = ValidatedToken {
+                timestamp,
+                offset: timestamp - current_time,
+                key_id: self.keyring.keys()[key as usize].id.clone(),
+            };
+            return Ok((validated, presented));
+        }
+
+        // Only reached for rejected tokens, so the extra MACs don't slow down the happy path
+        for distance in 1..=EXPIRY_LOOKAROUND {
+            for (key, _) in self.keyring.keys().iter().enumerate().filter(|(_, key)| key.is_live(now)) {
+                let past = -self.past_tolerance - distance;
+                if bool::from(self.sign(key, current_time + past, context).ct_eq(&presented)) {
+                    return Err(ValidationError::Expired { offset: past });
+                }
+                let future = self.future_tolerance + distance;
+                if bool::from(self.sign(key, current_time + future, context).ct_eq(&presented)) {
+                    return Err(ValidationError::NotYetValid { offset: future });
+                }
+            }
+        }
+
+        Err(ValidationError::Unknown)
+    }
+
+    /// Validates a `v1.<key-id>.<counter>.<mac>` token by recomputing a single MAC.
+    ///
+    /// The window check runs before the MAC is verified, so an [`Expired`](ValidationError::Expired) or
+    /// [`NotYetValid`](ValidationError::NotYetValid) result only reflects the unauthenticated counter in the token.
+    fn validate_versioned(
+        &self,
+        token: &str,
+        context: &TokenContext,
+        now: SystemTime,
+        current_time: i64,
+    ) -> Result<(ValidatedToken, Code), ValidationError> {
+        let parsed = VersionedToken::parse(self.algorithm, token).ok_or(ValidationError::Malformed)?;
+        let offset = parsed.counter.saturating_sub(current_time);
+        if offset < -self.past_tolerance {
+            return Err(ValidationError::Expired { offset });
+        }
+        if offset > self.future_tolerance {
+            return Err(ValidationError::NotYetValid { offset });
+        }
+
+        let keys = self.keyring.keys();
+        let key = keys
+            .iter()
+            .position(|key| key.is_live(now) && *key.id == *parsed.key_id)
+            .ok_or(ValidationError::Unknown)?;
+        let expected = self.encoding.sign(&self.keyed_macs[key], &keys[key].id, parsed.counter, context);
+        if !bool::from(expected.ct_eq(&parsed.mac)) {
+            return Err(ValidationError::Unknown);
+        }
+
+        let validated = ValidatedToken {
+            timestamp: parsed.counter,
+            offset,
+            key_id: keys[key].id.clone(),
+        };
+        Ok((validated, parsed.mac))
+    }
+
     pub fn is_valid(&mut self, token: &str) -> bool {
-        self.refresh_tokens();
-        self.active_tokens.iter().any(|t| t.token == token)
+        self.validate(token).is_ok()
+    }
+
+    pub fn is_valid_with_context(&mut self, token: &str, context: &TokenContext) -> bool {
+        self.validate_with_context(token, context).is_ok()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     fn test_token_validation() {
         let mut manager = RollingTokenManager::new("test_secret", 30, Some(1));
-        let token = manager.generate_token();
-        assert!(manager.is_valid(&token.token));
-        assert!(token.get_offset(&manager) == 0);
+        let token = manager.generate_token().unwrap();
+        assert!(manager.is_valid(token.as_str()));
+        assert_eq!(manager.validate(token.as_str()).map(|v| v.offset), Ok(0));
+
+        let token_offset_1 = manager.generate_token_with_offset(1).unwrap();
+        assert!(manager.is_valid(token_offset_1.as_str()));
+        assert_eq!(manager.validate(token_offset_1.as_str()).map(|v| v.offset), Ok(1));
+
+        let token_offset_2 = manager.generate_token_with_offset(2).unwrap();
+        assert!(!manager.is_valid(token_offset_2.as_str())); // token is too far in the future -> invalid
+        assert_eq!(
+            manager.validate(token_offset_2.as_str()).map(|v| v.offset),
+            Err(ValidationError::NotYetValid { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn test_offset_out_of_range() {
+        let manager = RollingTokenManager::new("test_secret", 30, Some(1));
+        assert_eq!(
+            manager.generate_token_with_offset(i64::MAX).err(),
+            Some(GenerateError::InvalidOffset)
+        );
+        assert_eq!(
+            manager.generate_token_bytes_with_offset(i64::MAX).err(),
+            Some(GenerateError::InvalidOffset)
+        );
 
-        let token_offset_1 = manager.generate_token_with_offset(1);
-        assert!(manager.is_valid(&token_offset_1.token));
-        assert!(token_offset_1.get_offset(&manager) == 1);
+        let early = manager.with_clock(MockClock::new(UNIX_EPOCH - Duration::from_secs(1)));
+        assert_eq!(early.generate_token_with_offset(i64::MIN).err(), Some(GenerateError::InvalidOffset));
+        assert!(early.generate_token_with_offset(i64::MIN + 1).is_ok());
+    }
+
+    #[test]
+    fn test_clock_at_the_edge_of_time() {
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(i64::MAX as u64));
+        let mut manager = RollingTokenManager::builder("test_secret")
+            .interval_secs(1)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert_eq!(manager.validate(&"0".repeat(64)), Err(ValidationError::Clock(ClockError)));
+        assert_eq!(manager.generate_token().err(), Some(GenerateError::Clock(ClockError)));
+        let shared = manager.clone().into_shared();
+        assert_eq!(shared.validate(&"0".repeat(64)), Err(ValidationError::Clock(ClockError)));
 
-        let token_offset_2 = manager.generate_token_with_offset(2);
-        assert!(!manager.is_valid(&token_offset_2.token)); // token is too far in the future -> invalid
-        assert!(token_offset_2.get_offset(&manager) == 2);
+        // The last interval whose window and lookaround fit
+        clock.set(UNIX_EPOCH + Duration::from_secs((i64::MAX - 1 - EXPIRY_LOOKAROUND) as u64));
+        let token = manager.generate_token().unwrap();
+        assert_eq!(manager.validate(token.as_str()).map(|v| v.offset), Ok(0));
+        assert_eq!(manager.validate(&"0".repeat(64)), Err(ValidationError::Unknown));
     }
 
     #[test]
     fn test_invalid_token() {
         let mut manager = RollingTokenManager::new("test_secret", 30, Some(1));
         assert!(!manager.is_valid("invalid_token"));
+        assert!(!manager.is_valid(""));
+        assert!(!manager.is_valid(&"0".repeat(64)));
+
+        let token = manager.generate_token().unwrap();
+        assert!(!manager.is_valid(&token.as_str()[..token.as_str().len() - 2])); // truncated
+        assert!(!manager.is_valid(&format!("{}00", token.as_str()))); // too long
+    }
+
+    #[test]
+    fn test_validation_result() {
+        let clock = MockClock::from_unix_secs(3000);
+        let mut manager = RollingTokenManager::new("test_secret", 30, Some(1)).with_clock(clock);
+
+        let previous = manager.generate_token_with_offset(-1).unwrap();
+        assert_eq!(
+            manager.validate(previous.as_str()),
+            Ok(ValidatedToken {
+                timestamp: 99,
+                offset: -1,
+                key_id: DEFAULT_KEY_ID.into(),
+            })
+        );
+
+        let expired = manager.generate_token_with_offset(-3).unwrap();
+        assert_eq!(manager.validate(expired.as_str()), Err(ValidationError::Expired { offset: -3 }));
+
+        let future = manager.generate_token_with_offset(2).unwrap();
+        assert_eq!(manager.validate(future.as_str()), Err(ValidationError::NotYetValid { offset: 2 }));
+
+        let other = RollingTokenManager::new("other_secret", 30, Some(1)).generate_token().unwrap();
+        assert_eq!(manager.validate(other.as_str()), Err(ValidationError::Unknown));
+
+        assert_eq!(manager.validate("invalid_token"), Err(ValidationError::Malformed));
+    }
+
+    #[test]
+    fn test_asymmetric_tolerance() {
+        let mut manager = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .past_tolerance(3)
+            .future_tolerance(0)
+            .clock(MockClock::from_unix_secs(3000))
+            .build()
+            .unwrap();
+
+        let old = manager.generate_token_with_offset(-3).unwrap();
+        assert_eq!(manager.validate(old.as_str()).map(|v| v.offset), Ok(-3));
+
+        let too_old = manager.generate_token_with_offset(-4).unwrap();
+        assert_eq!(manager.validate(too_old.as_str()), Err(ValidationError::Expired { offset: -4 }));
+
+        let next = manager.generate_token_with_offset(1).unwrap();
+        assert_eq!(manager.validate(next.as_str()), Err(ValidationError::NotYetValid { offset: 1 }));
+    }
+
+    #[test]
+    fn test_sub_second_interval() {
+        let clock = MockClock::from_unix_secs(10);
+        let mut manager = RollingTokenManager::builder("test_secret")
+            .interval(Duration::from_millis(500))
+            .tolerance(0)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        let token = manager.generate_token().unwrap();
+        assert_eq!(token.interval_index().unwrap(), 20);
+        clock.advance(Duration::from_millis(499));
+        assert!(manager.is_valid(token.as_str()));
+        clock.advance(Duration::from_millis(1));
+        assert!(!manager.is_valid(token.as_str()));
+    }
+
+    #[test]
+    fn test_second_aligned_interval_encoding() {
+        let manager = RollingTokenManager::builder("test_secret")
+            .interval(Duration::from_secs(30))
+            .clock(MockClock::new(UNIX_EPOCH + Duration::from_millis(3_029_999)))
+            .build()
+            .unwrap();
+
+        let token = manager.generate_token().unwrap();
+        assert_eq!(token.interval_index().unwrap(), 100);
+        assert_eq!(token.as_str(), "9fe73a0936b9b42298e3d517aadbdc133e681f7b318a66ed10e5e56d5ec8c86b");
+    }
+
+    #[test]
+    fn test_custom_epoch() {
+        let clock = MockClock::from_unix_secs(3600 + 14 * 60);
+        let mut manager = RollingTokenManager::builder("test_secret")
+            .interval_secs(3600)
+            .tolerance(0)
+            .epoch(UNIX_EPOCH + Duration::from_secs(15 * 60))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        let token = manager.generate_token().unwrap();
+        assert_eq!(token.interval_index().unwrap(), 0);
+        clock.advance(Duration::from_secs(60)); // rolls over at a quarter past
+        assert_eq!(manager.validate(token.as_str()), Err(ValidationError::Expired { offset: -1 }));
+
+        // Before the epoch, intervals count down from -1
+        clock.set(UNIX_EPOCH);
+        let early = manager.generate_token().unwrap();
+        assert_eq!(early.interval_index(), Some(-1));
+        assert_eq!(early.valid_from(), Some(UNIX_EPOCH - Duration::from_secs(45 * 60)));
+        assert_eq!(manager.validate(early.as_str()).map(|v| v.offset), Ok(0));
+    }
+
+    #[test]
+    fn test_versioned_tokens() {
+        let clock = MockClock::from_unix_secs(3000);
+        let keyring = Keyring::new().with_key("key.2024", "new_secret", KeyState::Primary).with_key(
+            DEFAULT_KEY_ID,
+            "test_secret",
+            KeyState::VerifyOnly,
+        );
+        let mut manager = RollingTokenManager::keyring_builder(keyring)
+            .interval_secs(30)
+            .encoding(Encoding::Versioned)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        let token = manager.generate_token_with_offset(-1).unwrap();
+        assert!(token.as_str().starts_with("v1.key.2024.99."));
+        let validated = manager.validate(token.as_str()).unwrap();
+        assert_eq!((validated.timestamp, validated.offset, &*validated.key_id), (99, -1, "key.2024"));
+
+        let tampered = token.as_str().replace(".99.", ".100.");
+        assert_eq!(manager.validate(&tampered), Err(ValidationError::Unknown));
+        let old = manager.generate_token_with_offset(-2).unwrap();
+        assert_eq!(manager.validate(old.as_str()), Err(ValidationError::Expired { offset: -2 }));
+        assert_eq!(manager.validate("v1.key.2024.100"), Err(ValidationError::Malformed));
+        for noncanonical in [
+            token.as_str().replace(".99.", ".099."),
+            token.as_str().replace(".99.", ".+99."),
+            token.as_str().to_uppercase().replacen("V1.KEY", "v1.key", 1),
+        ] {
+            assert_eq!(manager.validate(&noncanonical), Err(ValidationError::Malformed), "{noncanonical}");
+        }
+
+        let legacy = RollingTokenManager::new("test_secret", 30, None).with_clock(clock.clone());
+        let legacy = legacy.generate_token().unwrap();
+        assert_eq!(manager.validate(legacy.as_str()), Err(ValidationError::Malformed));
+
+        let mut accepting = RollingTokenManager::keyring_builder(Keyring::single(b"test_secret".to_vec()))
+            .interval_secs(30)
+            .encoding(Encoding::Versioned)
+            .accept_legacy_hex(true)
+            .clock(clock)
+            .build()
+            .unwrap();
+        assert!(accepting.is_valid(legacy.as_str()));
+        assert!(accepting.is_valid(accepting.generate_token().unwrap().as_str()));
+    }
+
+    #[test]
+    fn test_interval_boundary_with_mock_clock() {
+        let clock = MockClock::from_unix_secs(59);
+        let mut manager = RollingTokenManager::new("test_secret", 30, Some(1)).with_clock(clock.clone());
+        let token = manager.generate_token().unwrap();
+        assert_eq!(token.interval_index().unwrap(), 1);
+
+        clock.advance(Duration::from_secs(1)); // next interval starts
+        assert!(manager.is_valid(token.as_str()));
+        assert_eq!(manager.validate(token.as_str()).map(|v| v.offset), Ok(-1));
+
+        clock.advance(Duration::from_secs(30));
+        assert!(!manager.is_valid(token.as_str())); // token is two intervals old -> invalid
+    }
+
+    #[test]
+    fn test_clock_before_unix_epoch() {
+        let clock = MockClock::new(UNIX_EPOCH - Duration::from_millis(1));
+        let mut manager = RollingTokenManager::new("test_secret", 30, Some(1)).with_clock(clock.clone());
+        let token = manager.generate_token().unwrap();
+        assert_eq!(token.interval_index(), Some(-1));
+        assert_eq!(manager.validate(token.as_str()).map(|v| v.offset), Ok(0));
+
+        clock.set(UNIX_EPOCH - Duration::from_secs(30));
+        assert_eq!(manager.generate_token().unwrap().interval_index(), Some(-1));
+        clock.set(UNIX_EPOCH - Duration::from_millis(30_001));
+        assert_eq!(manager.validate(token.as_str()).map(|v| v.offset), Ok(1));
+        clock.set(UNIX_EPOCH);
+        assert_eq!(manager.validate(token.as_str()).map(|v| v.offset), Ok(-1));
     }
 }
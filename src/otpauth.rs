@@ -0,0 +1,285 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
+
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::{Algorithm, ConfigError, Encoding, GenerateError, RollingTokenManager, RollingTokenManagerBuilder, SecretEncoding};
+
+/// Characters escaped in labels and parameter values: everything except RFC 3986 unreserved characters.
+const ESCAPE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// An `otpauth://totp/...` provisioning URI, as rendered into QR codes for authenticator apps.
+#[derive(Clone, PartialEq, Eq)]
+pub struct OtpAuthUri {
+    pub issuer: Option<String>,
+    pub account: String,
+    pub secret: Vec<u8>,
+    pub algorithm: Algorithm,
+    pub digits: u8,
+    /// Interval in seconds.
+    pub period: u64,
+}
+
+impl OtpAuthUri {
+    pub fn parse(uri: &str) -> Result<Self, OtpAuthError> {
+        let rest = uri.strip_prefix("otpauth://").ok_or(OtpAuthError::InvalidScheme)?;
+        let (kind, rest) = rest.split_once('/').ok_or(OtpAuthError::InvalidScheme)?;
+        if !kind.eq_ignore_ascii_case("totp") {
+            return Err(OtpAuthError::UnsupportedType);
+        }
+
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+        // Split before decoding, since escaped colons belong to the issuer or account
+        let (mut issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(decode(issuer, "label")?), decode(account, "label")?.trim_start().to_string()),
+            None => (None, decode(label, "label")?),
+        };
+
+        let mut secret = None;
+        let mut algorithm = Algorithm::Sha1;
+        let mut digits = 6;
+        let mut period = 30;
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = decode(value, key)?;
+            match key {
+                "secret" => {
+                    // Authenticator apps commonly show secrets in lowercase, with spaces or padding
+                    secret = Some(SecretEncoding::Base32.decode(value.as_bytes()).ok_or(OtpAuthError::InvalidSecret)?);
+                }
+                "issuer" => issuer = Some(value),
+                "algorithm" => algorithm = value.parse().map_err(|_| OtpAuthError::InvalidParameter(key.to_string()))?,
+                "digits" => digits = value.parse().map_err(|_| OtpAuthError::InvalidParameter(key.to_string()))?,
+                "period" => period = value.parse().map_err(|_| OtpAuthError::InvalidParameter(key.to_string()))?,
+                _ => {} // e.g. `image`, which only matters to apps
+            }
+        }
+
+        Ok(Self {
+            issuer,
+            account,
+            secret: secret.ok_or(OtpAuthError::MissingSecret)?,
+            algorithm,
+            digits,
+            period,
+        })
+    }
+
+    /// Returns a builder configured for this URI, e.g. to adjust the tolerance before building.
+    pub fn builder(&self) -> RollingTokenManagerBuilder {
+        RollingTokenManager::builder(self.secret.clone())
+            .algorithm(self.algorithm)
+            .encoding(Encoding::Totp { digits: self.digits })
+            .interval(Duration::from_secs(self.period))
+    }
+}
+
+fn decode(value: &str, parameter: &str) -> Result<String, OtpAuthError> {
+    percent_decode_str(value)
+        .decode_utf8()
+        .map(|value| value.into_owned())
+        .map_err(|_| OtpAuthError::InvalidParameter(parameter.to_string()))
+}
+
+impl fmt::Display for OtpAuthUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "otpauth://totp/")?;
+        if let Some(issuer) = &self.issuer {
+            write!(f, "{}:", utf8_percent_encode(issuer, ESCAPE))?;
+        }
+        write!(
+            f,
+            "{}?secret={}",
+            utf8_percent_encode(&self.account, ESCAPE),
+            BASE32_NOPAD.encode(&self.secret)
+        )?;
+        if let Some(issuer) = &self.issuer {
+            write!(f, "&issuer={}", utf8_percent_encode(issuer, ESCAPE))?;
+        }
+        write!(f, "&algorithm={}&digits={}&period={}", self.algorithm, self.digits, self.period)
+    }
+}
+
+/// Redacts the secret.
+impl fmt::Debug for OtpAuthUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OtpAuthUri")
+            .field("issuer", &self.issuer)
+            .field("account", &self.account)
+            .field("algorithm", &self.algorithm)
+            .field("digits", &self.digits)
+            .field("period", &self.period)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for OtpAuthUri {
+    type Err = OtpAuthError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        Self::parse(uri)
+    }
+}
+
+impl RollingTokenManager {
+    /// Builds a provisioning URI for enrolling this manager's current signing secret in an authenticator app.
+    ///
+    /// Fails unless the manager uses [`Encoding::Totp`] with an HMAC-SHA1/SHA256/SHA512 algorithm, a whole-second
+    /// interval and the Unix epoch, since authenticator apps can't represent anything else.
+    pub fn otpauth_uri(&self, issuer: Option<&str>, account: &str) -> Result<OtpAuthUri, OtpAuthError> {
+        let Encoding::Totp { digits } = self.encoding else {
+            return Err(OtpAuthError::Incompatible);
+        };
+        let standard_algorithm = matches!(self.algorithm, Algorithm::Sha1 | Algorithm::Sha256 | Algorithm::Sha512);
+        if !standard_algorithm || self.interval_ms % 1000 != 0 || self.epoch != UNIX_EPOCH {
+            return Err(OtpAuthError::Incompatible);
+        }
+
+        Ok(OtpAuthUri {
+            issuer: issuer.map(str::to_string),
+            account: account.to_string(),
+            secret: self.keyring.keys()[self.signing_key(self.clock.now())?].secret.expose().to_vec(),
+            algorithm: self.algorithm,
+            digits,
+            period: self.interval_ms as u64 / 1000,
+        })
+    }
+
+    /// Creates a manager from an `otpauth://totp/...` URI with the default tolerance.
+    pub fn from_otpauth_uri(uri: &str) -> Result<Self, OtpAuthError> {
+        Ok(OtpAuthUri::parse(uri)?.builder().build()?)
+    }
+}
+
+/// Error converting between a [`RollingTokenManager`] and an `otpauth://` URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OtpAuthError {
+    /// The URI does not start with `otpauth://<type>/`.
+    InvalidScheme,
+    /// The URI is not of type `totp`.
+    UnsupportedType,
+    /// The URI has no `secret` parameter.
+    MissingSecret,
+    /// The `secret` parameter is not valid base32.
+    InvalidSecret,
+    /// The named parameter could not be parsed.
+    InvalidParameter(String),
+    /// The manager's configuration cannot be expressed as a TOTP URI.
+    Incompatible,
+    /// The URI describes an invalid manager configuration.
+    Config(ConfigError),
+    /// The manager has no secret to export at the current time.
+    Generate(GenerateError),
+}
+
+impl fmt::Display for OtpAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidScheme => write!(f, "not an otpauth:// URI"),
+            Self::UnsupportedType => write!(f, "only totp URIs are supported"),
+            Self::MissingSecret => write!(f, "URI has no secret"),
+            Self::InvalidSecret => write!(f, "secret is not valid base32"),
+            Self::InvalidParameter(parameter) => write!(f, "invalid {parameter} in URI"),
+            Self::Incompatible => write!(f, "manager is not compatible with authenticator apps"),
+            Self::Config(err) => write!(f, "invalid configuration in URI: {err}"),
+            Self::Generate(err) => write!(f, "cannot export secret: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for OtpAuthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Config(err) => Some(err),
+            Self::Generate(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<GenerateError> for OtpAuthError {
+    fn from(err: GenerateError) -> Self {
+        Self::Generate(err)
+    }
+}
+
+impl From<ConfigError> for OtpAuthError {
+    fn from(err: ConfigError) -> Self {
+        Self::Config(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_authenticator_uri() {
+        let uri = OtpAuthUri::parse("otpauth://totp/Example:alice%40google.com?secret=jbswy3dpehpk3pxp&issuer=Example").unwrap();
+        assert_eq!(uri.issuer.as_deref(), Some("Example"));
+        assert_eq!(uri.account, "alice@google.com");
+        assert_eq!(uri.secret, b"Hello!\xde\xad\xbe\xef");
+        assert_eq!((uri.algorithm, uri.digits, uri.period), (Algorithm::Sha1, 6, 30));
+    }
+
+    #[test]
+    fn test_roundtrip_through_manager() {
+        let manager = RollingTokenManager::builder("12345678901234567890")
+            .interval_secs(60)
+            .algorithm(Algorithm::Sha256)
+            .encoding(Encoding::Totp { digits: 8 })
+            .build()
+            .unwrap();
+
+        let uri = manager.otpauth_uri(Some("ACME Co"), "john doe").unwrap().to_string();
+        assert_eq!(
+            uri,
+            "otpauth://totp/ACME%20Co:john%20doe?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60"
+        );
+
+        let mut restored = RollingTokenManager::from_otpauth_uri(&uri).unwrap();
+        assert!(restored.is_valid(manager.generate_token().unwrap().as_str()));
+    }
+
+    #[test]
+    fn test_roundtrip_reserved_characters() {
+        let manager = RollingTokenManager::builder("12345678901234567890")
+            .interval_secs(30)
+            .encoding(Encoding::Totp { digits: 6 })
+            .build()
+            .unwrap();
+
+        let uri = manager.otpauth_uri(Some("A:B"), "acct:1 ?&=").unwrap();
+        let parsed = OtpAuthUri::parse(&uri.to_string()).unwrap();
+        assert_eq!(parsed.issuer.as_deref(), Some("A:B"));
+        assert_eq!(parsed.account, "acct:1 ?&=");
+        assert_eq!(parsed, uri);
+
+        let uri = manager.otpauth_uri(None, "a:b").unwrap();
+        assert_eq!(OtpAuthUri::parse(&uri.to_string()).unwrap(), uri);
+    }
+
+    #[test]
+    fn test_invalid_uris() {
+        assert_eq!(OtpAuthUri::parse("https://example.com").err(), Some(OtpAuthError::InvalidScheme));
+        assert_eq!(
+            OtpAuthUri::parse("otpauth://hotp/a?secret=AA").err(),
+            Some(OtpAuthError::UnsupportedType)
+        );
+        assert_eq!(OtpAuthUri::parse("otpauth://totp/a").err(), Some(OtpAuthError::MissingSecret));
+        assert_eq!(
+            OtpAuthUri::parse("otpauth://totp/a?secret=1").err(),
+            Some(OtpAuthError::InvalidSecret)
+        );
+        assert_eq!(
+            RollingTokenManager::from_otpauth_uri("otpauth://totp/a?secret=GEZDGNBV&digits=4").err(),
+            Some(OtpAuthError::Config(ConfigError::InvalidDigits))
+        );
+        assert_eq!(
+            RollingTokenManager::new("secret", 30, None).otpauth_uri(None, "a").err(),
+            Some(OtpAuthError::Incompatible)
+        );
+    }
+}
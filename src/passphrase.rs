@@ -0,0 +1,313 @@
+use std::fmt;
+use std::str::FromStr;
+
+use data_encoding::BASE64_NOPAD;
+use sha2::Sha256;
+
+use crate::{RollingTokenManager, RollingTokenManagerBuilder};
+
+/// Length of secrets derived from passphrases.
+const DERIVED_LEN: usize = 32;
+
+/// Shortest accepted salt, as required by Argon2.
+pub const MIN_SALT_LEN: usize = 8;
+
+/// Key derivation function and cost for turning a passphrase into a secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassphraseKdf {
+    /// Argon2id, version 0x13, with memory cost in KiB.
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+    /// PBKDF2 with HMAC-SHA256.
+    Pbkdf2Sha256 { iterations: u32 },
+}
+
+impl PassphraseKdf {
+    /// Argon2id with the minimum cost recommended by OWASP: 19 MiB of memory, 2 iterations and 1 lane.
+    pub const ARGON2ID: Self = Self::Argon2id {
+        memory_kib: 19 * 1024,
+        iterations: 2,
+        parallelism: 1,
+    };
+
+    /// PBKDF2-HMAC-SHA256 with the 600,000 iterations recommended by OWASP.
+    pub const PBKDF2_SHA256: Self = Self::Pbkdf2Sha256 { iterations: 600_000 };
+}
+
+/// Defaults to [`PassphraseKdf::ARGON2ID`].
+impl Default for PassphraseKdf {
+    fn default() -> Self {
+        Self::ARGON2ID
+    }
+}
+
+/// Everything needed besides the passphrase to derive the same secret everywhere: the KDF, its cost and the salt.
+///
+/// Parameters are not secret. They are written in the PHC string format without a hash, e.g.
+/// `$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ` or `$pbkdf2-sha256$i=600000$c2FsdHNhbHQ` with the salt in unpadded
+/// base64, so they can be stored next to a config and parsed back with [`FromStr`]. With the `serde` feature, they
+/// are serialized as that string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub struct PassphraseParams {
+    pub kdf: PassphraseKdf,
+    pub salt: Vec<u8>,
+}
+
+impl PassphraseParams {
+    /// Creates parameters with a salt, which should be random and shared by every side deriving the secret.
+    pub fn new(kdf: PassphraseKdf, salt: impl Into<Vec<u8>>) -> Self {
+        Self { kdf, salt: salt.into() }
+    }
+
+    /// Derives a 32-byte secret from `passphrase`. This is deliberately slow, so derive once and reuse the manager.
+    pub fn derive(&self, passphrase: impl AsRef<[u8]>) -> Result<Vec<u8>, PassphraseError> {
+        let passphrase = passphrase.as_ref();
+        if passphrase.is_empty() {
+            return Err(PassphraseError::EmptyPassphrase);
+        }
+        if self.salt.len() < MIN_SALT_LEN {
+            return Err(PassphraseError::SaltTooShort);
+        }
+
+        let mut secret = vec![0; DERIVED_LEN];
+        match self.kdf {
+            PassphraseKdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = argon2::Params::new(memory_kib, iterations, parallelism, Some(DERIVED_LEN))
+                    .map_err(|_| PassphraseError::InvalidCost)?;
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(passphrase, &self.salt, &mut secret)
+                    .map_err(|_| PassphraseError::InvalidCost)?;
+            }
+            PassphraseKdf::Pbkdf2Sha256 { iterations } => {
+                if iterations == 0 {
+                    return Err(PassphraseError::InvalidCost);
+                }
+                pbkdf2::pbkdf2_hmac::<Sha256>(passphrase, &self.salt, iterations, &mut secret);
+            }
+        }
+        Ok(secret)
+    }
+}
+
+impl fmt::Display for PassphraseParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let salt = BASE64_NOPAD.encode(&self.salt);
+        match self.kdf {
+            PassphraseKdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => write!(f, "$argon2id$v=19$m={memory_kib},t={iterations},p={parallelism}${salt}"),
+            PassphraseKdf::Pbkdf2Sha256 { iterations } => write!(f, "$pbkdf2-sha256$i={iterations}${salt}"),
+        }
+    }
+}
+
+impl FromStr for PassphraseParams {
+    type Err = PassphraseError;
+
+    fn from_str(params: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = params.split('$').collect();
+        let (kdf, salt) = match fields.as_slice() {
+            ["", "argon2id", "v=19", cost, salt] => {
+                let [m, t, p] = parse_cost(cost, ["m", "t", "p"])?;
+                let kdf = PassphraseKdf::Argon2id {
+                    memory_kib: m,
+                    iterations: t,
+                    parallelism: p,
+                };
+                (kdf, salt)
+            }
+            ["", "pbkdf2-sha256", cost, salt] => {
+                let [i] = parse_cost(cost, ["i"])?;
+                (PassphraseKdf::Pbkdf2Sha256 { iterations: i }, salt)
+            }
+            _ => return Err(PassphraseError::Malformed),
+        };
+        let salt = BASE64_NOPAD.decode(salt.as_bytes()).map_err(|_| PassphraseError::Malformed)?;
+        Ok(Self { kdf, salt })
+    }
+}
+
+/// Parses `name=value` pairs in the given order, e.g. `m=19456,t=2,p=1`.
+fn parse_cost<const N: usize>(cost: &str, names: [&str; N]) -> Result<[u32; N], PassphraseError> {
+    let mut pairs = cost.split(',');
+    let mut values = [0; N];
+    for (value, name) in values.iter_mut().zip(names) {
+        let (key, number) = pairs
+            .next()
+            .and_then(|pair| pair.split_once('='))
+            .ok_or(PassphraseError::Malformed)?;
+        if key != name {
+            return Err(PassphraseError::Malformed);
+        }
+        *value = number.parse().map_err(|_| PassphraseError::Malformed)?;
+    }
+    match pairs.next() {
+        Some(_) => Err(PassphraseError::Malformed),
+        None => Ok(values),
+    }
+}
+
+impl From<PassphraseParams> for String {
+    fn from(params: PassphraseParams) -> Self {
+        params.to_string()
+    }
+}
+
+impl TryFrom<String> for PassphraseParams {
+    type Error = PassphraseError;
+
+    fn try_from(params: String) -> Result<Self, Self::Error> {
+        params.parse()
+    }
+}
+
+impl RollingTokenManager {
+    /// Creates a builder for a manager with a single secret derived from `passphrase` with `params`.
+    pub fn builder_from_passphrase(
+        passphrase: impl AsRef<[u8]>,
+        params: &PassphraseParams,
+    ) -> Result<RollingTokenManagerBuilder, PassphraseError> {
+        Ok(Self::builder(params.derive(passphrase)?))
+    }
+}
+
+/// A secret could not be derived from a passphrase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassphraseError {
+    /// The passphrase is empty.
+    EmptyPassphrase,
+    /// The salt is shorter than [`MIN_SALT_LEN`].
+    SaltTooShort,
+    /// The cost parameters are out of range for the KDF.
+    InvalidCost,
+    /// The parameter string is not in the expected PHC format.
+    Malformed,
+}
+
+impl fmt::Display for PassphraseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyPassphrase => write!(f, "passphrase must not be empty"),
+            Self::SaltTooShort => write!(f, "salt must be at least {MIN_SALT_LEN} bytes"),
+            Self::InvalidCost => write!(f, "invalid cost parameters for key derivation"),
+            Self::Malformed => write!(f, "malformed passphrase parameters"),
+        }
+    }
+}
+
+impl std::error::Error for PassphraseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockClock;
+
+    /// Cheap Argon2id cost, so tests run quickly.
+    const FAST_ARGON2ID: PassphraseKdf = PassphraseKdf::Argon2id {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_derive() {
+        let params = PassphraseParams::new(PassphraseKdf::Pbkdf2Sha256 { iterations: 1 }, "saltsalt");
+        assert_eq!(
+            hex::encode(params.derive("passwd").unwrap()),
+            "94398fe91b85307f185a879f884a2ae1e4ea297415e4e7a9d2fa22c6a327a41c"
+        );
+
+        let params = PassphraseParams::new(FAST_ARGON2ID, "somesalt");
+        let secret = params.derive("correct horse battery staple").unwrap();
+        assert_eq!(secret.len(), DERIVED_LEN);
+        assert_eq!(params.derive("correct horse battery staple").unwrap(), secret);
+        assert_ne!(params.derive("correct horse battery stapler").unwrap(), secret);
+        assert_ne!(
+            PassphraseParams::new(FAST_ARGON2ID, "othersalt")
+                .derive("correct horse battery staple")
+                .unwrap(),
+            secret
+        );
+    }
+
+    #[test]
+    fn test_params_roundtrip() {
+        let argon2id = PassphraseParams::new(PassphraseKdf::ARGON2ID, "saltsalt");
+        assert_eq!(argon2id.to_string(), "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ");
+        assert_eq!(argon2id.to_string().parse(), Ok(argon2id));
+
+        let pbkdf2 = PassphraseParams::new(PassphraseKdf::PBKDF2_SHA256, "saltsalt");
+        assert_eq!(pbkdf2.to_string(), "$pbkdf2-sha256$i=600000$c2FsdHNhbHQ");
+        assert_eq!(pbkdf2.to_string().parse(), Ok(pbkdf2));
+
+        for malformed in [
+            "",
+            "$argon2i$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ",
+            "$argon2id$v=16$m=19456,t=2,p=1$c2FsdHNhbHQ",
+            "$argon2id$v=19$t=2,m=19456,p=1$c2FsdHNhbHQ",
+            "$argon2id$v=19$m=19456,t=2,p=1,x=0$c2FsdHNhbHQ",
+            "$pbkdf2-sha256$i=-1$c2FsdHNhbHQ",
+            "$pbkdf2-sha256$i=600000$c2FsdHNhbHQ=",
+        ] {
+            assert_eq!(
+                malformed.parse::<PassphraseParams>(),
+                Err(PassphraseError::Malformed),
+                "{malformed}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_derivations() {
+        let params = PassphraseParams::new(FAST_ARGON2ID, "saltsalt");
+        assert_eq!(params.derive(""), Err(PassphraseError::EmptyPassphrase));
+        assert_eq!(
+            PassphraseParams::new(FAST_ARGON2ID, "salt").derive("passphrase"),
+            Err(PassphraseError::SaltTooShort)
+        );
+        let no_memory = PassphraseKdf::Argon2id {
+            memory_kib: 1,
+            iterations: 1,
+            parallelism: 1,
+        };
+        assert_eq!(
+            PassphraseParams::new(no_memory, "saltsalt").derive("passphrase"),
+            Err(PassphraseError::InvalidCost)
+        );
+        assert_eq!(
+            PassphraseParams::new(PassphraseKdf::Pbkdf2Sha256 { iterations: 0 }, "saltsalt").derive("passphrase"),
+            Err(PassphraseError::InvalidCost)
+        );
+    }
+
+    #[test]
+    fn test_builder_from_passphrase() {
+        let params: PassphraseParams = "$pbkdf2-sha256$i=1000$c2FsdHNhbHQ".parse().unwrap();
+        let build = |passphrase: &str| {
+            RollingTokenManager::builder_from_passphrase(passphrase, &params)
+                .unwrap()
+                .interval_secs(30)
+                .clock(MockClock::from_unix_secs(3000))
+                .build()
+                .unwrap()
+        };
+
+        let token = build("open sesame").generate_token().unwrap();
+        assert!(build("open sesame").is_valid(token.as_str()));
+        assert!(!build("open sesame!").is_valid(token.as_str()));
+    }
+}
@@ -0,0 +1,396 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    Clock, ConfigError, GenerateError, Key, KeyState, Keyring, RollingTokenManagerBuilder, SecretEncoding, SecretError, SecretSource,
+    SharedValidator, Token, TokenBytes, TokenContext, ValidatedToken, ValidationError,
+};
+
+/// Where a [`ReloadingValidator`] loads its keys from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// A single secret, e.g. a mounted secret file that is atomically replaced on rotation.
+    ///
+    /// Its key ID is a fingerprint of the secret, so that all instances loading the same secret agree on it.
+    Secret(SecretSource),
+    /// A directory with one secret per file, named by key ID. The key whose ID sorts last is the primary key, so
+    /// IDs should sort by age, e.g. `2024-06`. Hidden files, such as the `..data` links of Kubernetes, are skipped.
+    ///
+    /// When the contents of a file change, the previous secret stays verify-only for the grace period as
+    /// `<id>#<fingerprint>`. Versioned tokens name the key they were signed with, so with
+    /// [`Encoding::Versioned`](crate::Encoding::Versioned) rotate by adding a new file instead.
+    Directory { path: PathBuf, encoding: SecretEncoding },
+}
+
+impl KeySource {
+    /// Loads the keys, with the primary key last.
+    fn load(&self) -> Result<Vec<Key>, ConfigError> {
+        match self {
+            Self::Secret(source) => {
+                let secret = source.load()?;
+                Ok(vec![Key::new(fingerprint(&secret), secret, KeyState::Primary)])
+            }
+            Self::Directory { path, encoding } => {
+                let unreadable = |err: std::io::Error| SecretError::UnreadableFile {
+                    path: path.clone(),
+                    kind: err.kind(),
+                };
+                let mut files = Vec::new();
+                for entry in std::fs::read_dir(path).map_err(unreadable)? {
+                    let entry = entry.map_err(unreadable)?;
+                    let id = entry.file_name().to_string_lossy().into_owned();
+                    if !id.starts_with('.') && entry.path().is_file() {
+                        files.push((id, entry.path()));
+                    }
+                }
+                files.sort();
+
+                let primary = files.len().saturating_sub(1);
+                files
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (id, file))| {
+                        let state = if i == primary { KeyState::Primary } else { KeyState::VerifyOnly };
+                        Ok(Key::new(id, SecretSource::file(file).encoding(*encoding).load()?, state))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Key ID of a single reloaded secret.
+fn fingerprint(secret: &[u8]) -> String {
+    let digest = Sha256::new()
+        .chain_update(b"rolling-token-auth key id\0")
+        .chain_update(secret)
+        .finalize();
+    hex::encode(&digest[..8])
+}
+
+struct ReloadState {
+    /// The keys as last loaded from the source.
+    loaded: Vec<Key>,
+    /// Keys that disappeared from the source or whose secret was replaced, verify-only until their grace period ends.
+    retired: Vec<Key>,
+    last_error: Option<ConfigError>,
+}
+
+/// A [`SharedValidator`] whose keys are reloaded from files, e.g. mounted Kubernetes secrets.
+///
+/// Each reload that finds changed keys builds a new validator and swaps it in atomically, so validations in flight
+/// finish against the keys they started with. Keys that disappear or change stay accepted as verify-only for a grace
+/// period, which defaults to how long a token stays valid after its interval starts. A failed reload keeps the current
+/// keys and is reported by [`last_error`](Self::last_error).
+pub struct ReloadingValidator {
+    builder: RollingTokenManagerBuilder,
+    source: KeySource,
+    grace_period: Duration,
+    clock: Arc<dyn Clock>,
+    state: Mutex<ReloadState>,
+    current: ArcSwap<SharedValidator>,
+}
+
+impl ReloadingValidator {
+    /// Loads the keys from `source` and builds validators with the settings of `builder`, whose own keys are ignored.
+    pub fn new(builder: RollingTokenManagerBuilder, source: KeySource) -> Result<Self, ConfigError> {
+        let loaded = source.load()?;
+        let manager = builder.clone().keyring(keyring(&loaded, &[])).build()?;
+        // As a `Duration`, since the product can overflow `i64` milliseconds for long intervals
+        let grace_period = Duration::from_millis(manager.interval_ms as u64).saturating_mul(manager.past_tolerance as u32 + 1);
+
+        Ok(Self {
+            builder,
+            source,
+            grace_period,
+            clock: manager.clock.clone(),
+            state: Mutex::new(ReloadState {
+                loaded,
+                retired: Vec::new(),
+                last_error: None,
+            }),
+            current: ArcSwap::from_pointee(manager.into_shared()),
+        })
+    }
+
+    /// Sets how long keys that disappear from the source or change keep verifying tokens. It should cover how long
+    /// other instances may still sign with them, e.g. until they reload too.
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Reloads the keys, returning whether they changed.
+    ///
+    /// On failure, the current keys stay in use and the error is also kept for [`last_error`](Self::last_error).
+    pub fn reload(&self) -> Result<bool, ConfigError> {
+        let mut state = self.state.lock().unwrap();
+        let result = self.try_reload(&mut state);
+        state.last_error = result.as_ref().err().cloned();
+        result
+    }
+
+    fn try_reload(&self, state: &mut ReloadState) -> Result<bool, ConfigError> {
+        let loaded = self.source.load()?;
+        let unchanged = loaded.len() == state.loaded.len() && loaded.iter().zip(&state.loaded).all(|(a, b)| same_key(a, b));
+        if unchanged {
+            return Ok(false);
+        }
+
+        let now = self.clock.now();
+        let mut retired: Vec<Key> = state.retired.iter().filter(|key| key.is_live(now)).cloned().collect();
+        for key in &state.loaded {
+            let id = match loaded.iter().find(|new| new.id == key.id) {
+                None => key.id.clone(),
+                Some(new) if new.secret.expose() != key.secret.expose() => replaced_id(key),
+                Some(_) => continue,
+            };
+            let mut key = key.clone();
+            key.id = id;
+            key.state = KeyState::VerifyOnly;
+            key.not_after = now.checked_add(self.grace_period);
+            retired.retain(|other| other.id != key.id);
+            retired.push(key);
+        }
+        // A key that reappears is loaded again rather than retired
+        retired.retain(|key| !loaded.iter().any(|new| new.id == key.id || replaced_id(new) == key.id));
+
+        let manager = self.builder.clone().keyring(keyring(&loaded, &retired)).build()?;
+        let validator = manager.into_shared().share_replay_cache(&self.current.load());
+        self.current.store(Arc::new(validator));
+        state.loaded = loaded;
+        state.retired = retired;
+        Ok(true)
+    }
+
+    /// The error of the last reload, or `None` if it succeeded.
+    pub fn last_error(&self) -> Option<ConfigError> {
+        self.state.lock().unwrap().last_error.clone()
+    }
+
+    /// Reloads the keys every `poll_interval` on a background thread, which stops once the validator is dropped.
+    pub fn watch(self: &Arc<Self>, poll_interval: Duration) -> thread::JoinHandle<()> {
+        let validator: Weak<Self> = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(poll_interval);
+            match validator.upgrade() {
+                // Failures are kept for `last_error`
+                Some(validator) => drop(validator.reload()),
+                None => break,
+            }
+        })
+    }
+
+    /// The validator for the current keys. It keeps working with these keys after a reload.
+    pub fn current(&self) -> Arc<SharedValidator> {
+        self.current.load_full()
+    }
+
+    pub fn generate_token(&self) -> Result<Token, GenerateError> {
+        self.current.load().generate_token()
+    }
+
+    pub fn generate_token_with_offset(&self, offset: i64) -> Result<Token, GenerateError> {
+        self.current.load().generate_token_with_offset(offset)
+    }
+
+    pub fn generate_token_with_context(&self, context: &TokenContext) -> Result<Token, GenerateError> {
+        self.current.load().generate_token_with_context(context)
+    }
+
+    pub fn generate_token_bytes(&self) -> Result<TokenBytes, GenerateError> {
+        self.current.load().generate_token_bytes()
+    }
+
+    /// See [`RollingTokenManager::validate`](crate::RollingTokenManager::validate).
+    pub fn validate(&self, token: &str) -> Result<ValidatedToken, ValidationError> {
+        self.current.load().validate(token)
+    }
+
+    /// See [`RollingTokenManager::validate_with_context`](crate::RollingTokenManager::validate_with_context).
+    pub fn validate_with_context(&self, token: &str, context: &TokenContext) -> Result<ValidatedToken, ValidationError> {
+        self.current.load().validate_with_context(token, context)
+    }
+
+    /// See [`RollingTokenManager::validate_bytes`](crate::RollingTokenManager::validate_bytes).
+    pub fn validate_bytes(&self, token: &TokenBytes) -> Result<ValidatedToken, ValidationError> {
+        self.current.load().validate_bytes(token)
+    }
+
+    pub fn is_valid(&self, token: &str) -> bool {
+        self.validate(token).is_ok()
+    }
+
+    pub fn is_valid_with_context(&self, token: &str, context: &TokenContext) -> bool {
+        self.validate_with_context(token, context).is_ok()
+    }
+}
+
+fn keyring(loaded: &[Key], retired: &[Key]) -> Keyring {
+    let mut keyring = Keyring::new();
+    loaded.iter().chain(retired).for_each(|key| keyring.push(key.clone()));
+    keyring
+}
+
+/// ID of a key whose secret was replaced in place, which stays accepted under this ID for the grace period.
+fn replaced_id(key: &Key) -> Arc<str> {
+    format!("{}#{}", key.id, fingerprint(key.secret.expose())).into()
+}
+
+fn same_key(a: &Key, b: &Key) -> bool {
+    a.id == b.id && a.state == b.state && a.secret.expose() == b.secret.expose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::{MockClock, RollingTokenManager};
+    use std::fs;
+
+    fn builder(clock: &MockClock) -> RollingTokenManagerBuilder {
+        RollingTokenManager::keyring_builder(Keyring::new())
+            .interval_secs(30)
+            .clock(clock.clone())
+    }
+
+    #[test]
+    fn test_reload_secret_file() {
+        let path = TempPath::file("reload", "secret_a\n");
+        let clock = MockClock::from_unix_secs(3000);
+        let validator = ReloadingValidator::new(builder(&clock), KeySource::Secret(SecretSource::file(&*path))).unwrap();
+        assert_eq!(validator.reload(), Ok(false));
+
+        let token_a = validator.generate_token().unwrap();
+        let key_a = validator.validate(token_a.as_str()).unwrap().key_id;
+        let snapshot = validator.current();
+
+        fs::write(&path, "secret_b\n").unwrap();
+        assert_eq!(validator.reload(), Ok(true));
+        let token_b = validator.generate_token().unwrap();
+        assert_ne!(token_b, token_a);
+        assert_eq!(validator.validate(token_a.as_str()).unwrap().key_id, key_a); // within the grace period
+        assert!(snapshot.is_valid(token_a.as_str()) && !snapshot.is_valid(token_b.as_str()));
+
+        // The default grace period covers the past tolerance
+        clock.advance(Duration::from_secs(59));
+        assert!(validator.is_valid(token_a.as_str()));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(validator.validate(token_a.as_str()), Err(ValidationError::Unknown)); // key a is gone
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            validator.reload(),
+            Err(ConfigError::Secret(SecretError::UnreadableFile { .. }))
+        ));
+        assert!(validator.last_error().is_some());
+        let token_b = validator.generate_token().unwrap();
+        assert!(validator.is_valid(token_b.as_str())); // the last good key is kept
+    }
+
+    #[test]
+    fn test_reload_directory() {
+        let dir = TempPath::new("keyring");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("2024-01"), "secret_1").unwrap();
+        fs::write(dir.join("2024-06"), "secret_2").unwrap();
+        fs::write(dir.join(".hidden"), "ignored").unwrap();
+        let clock = MockClock::from_unix_secs(3000);
+        let source = KeySource::Directory {
+            path: dir.to_path_buf(),
+            encoding: SecretEncoding::Raw,
+        };
+        let validator = ReloadingValidator::new(builder(&clock), source)
+            .unwrap()
+            .grace_period(Duration::from_secs(600));
+
+        let token = validator.generate_token().unwrap();
+        assert_eq!(&*validator.validate(token.as_str()).unwrap().key_id, "2024-06");
+
+        fs::remove_file(dir.join("2024-06")).unwrap();
+        assert_eq!(validator.reload(), Ok(true));
+        assert_eq!(&*validator.validate(token.as_str()).unwrap().key_id, "2024-06");
+        let next = validator.generate_token().unwrap();
+        assert_eq!(&*validator.validate(next.as_str()).unwrap().key_id, "2024-01");
+
+        clock.advance(Duration::from_secs(600));
+        let reference = RollingTokenManager::new("secret_2", 30, None).with_clock(clock.clone());
+        assert_eq!(
+            validator.validate(reference.generate_token().unwrap().as_str()),
+            Err(ValidationError::Unknown)
+        );
+    }
+
+    #[test]
+    fn test_reload_changed_file() {
+        let dir = TempPath::new("changed");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("current"), "secret_1").unwrap();
+        let clock = MockClock::from_unix_secs(3000);
+        let source = KeySource::Directory {
+            path: dir.to_path_buf(),
+            encoding: SecretEncoding::Raw,
+        };
+        let validator = ReloadingValidator::new(builder(&clock), source).unwrap();
+        let old = validator.generate_token().unwrap();
+
+        fs::write(dir.join("current"), "secret_2").unwrap();
+        assert_eq!(validator.reload(), Ok(true));
+        let retired_id = format!("current#{}", fingerprint(b"secret_1"));
+        assert_eq!(*validator.validate(old.as_str()).unwrap().key_id, *retired_id);
+        let new = validator.generate_token().unwrap();
+        assert_eq!(&*validator.validate(new.as_str()).unwrap().key_id, "current");
+
+        // Changing it again keeps both previous secrets for their grace periods
+        fs::write(dir.join("current"), "secret_3").unwrap();
+        assert_eq!(validator.reload(), Ok(true));
+        assert!(validator.is_valid(old.as_str()) && validator.is_valid(new.as_str()));
+
+        // Reverting loads the secret again instead of keeping a retired copy
+        fs::write(dir.join("current"), "secret_1").unwrap();
+        assert_eq!(validator.reload(), Ok(true));
+        assert_eq!(&*validator.validate(old.as_str()).unwrap().key_id, "current");
+        assert!(validator.state.lock().unwrap().retired.iter().all(|key| *key.id != *retired_id));
+
+        clock.advance(Duration::from_secs(60));
+        let reference = RollingTokenManager::new("secret_2", 30, None).with_clock(clock.clone());
+        assert_eq!(
+            validator.validate(reference.generate_token().unwrap().as_str()),
+            Err(ValidationError::Unknown)
+        );
+    }
+
+    #[test]
+    fn test_default_grace_period_of_long_interval() {
+        let builder = builder(&MockClock::from_unix_secs(3000))
+            .interval(Duration::from_millis(i64::MAX as u64 / 2))
+            .tolerance(3);
+        let validator = ReloadingValidator::new(builder, KeySource::Secret(SecretSource::literal("secret"))).unwrap();
+        assert_eq!(validator.grace_period, Duration::from_millis(i64::MAX as u64 / 2) * 4);
+    }
+
+    #[test]
+    fn test_watch() {
+        let path = TempPath::file("watch", "secret_a");
+        let source = KeySource::Secret(SecretSource::file(&*path));
+        let validator = Arc::new(ReloadingValidator::new(builder(&MockClock::from_unix_secs(3000)), source).unwrap());
+        let handle = validator.watch(Duration::from_millis(5));
+
+        let token_a = validator.generate_token().unwrap();
+        fs::write(&path, "secret_b").unwrap();
+        let mut polls = 0;
+        while validator.generate_token().unwrap() == token_a && polls < 1000 {
+            thread::sleep(Duration::from_millis(5));
+            polls += 1;
+        }
+        assert_ne!(validator.generate_token().unwrap(), token_a, "not reloaded within 5 seconds");
+
+        drop(validator);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use crate::encoding::Code;
+use crate::{ValidatedToken, ValidationError};
+
+/// Tokens accepted in one-time-use mode, grouped by interval so expired intervals can be dropped cheaply.
+#[derive(Default)]
+pub(crate) struct ReplayCache {
+    seen: BTreeMap<i64, HashSet<(Arc<str>, Code)>>,
+}
+
+impl ReplayCache {
+    /// Records a validated token by its canonical code rather than its string, so e.g. re-encoding the hex in
+    /// uppercase doesn't bypass the cache. Fails if it was already recorded.
+    pub(crate) fn check(&mut self, validated: &ValidatedToken, code: Code, past_tolerance: i64) -> Result<(), ValidationError> {
+        // Tokens of intervals that left the tolerance window are rejected anyway
+        let oldest_valid = validated.timestamp - validated.offset - past_tolerance;
+        self.seen = self.seen.split_off(&oldest_valid);
+
+        let entry = (validated.key_id.clone(), code);
+        if self.seen.entry(validated.timestamp).or_default().insert(entry) {
+            Ok(())
+        } else {
+            Err(ValidationError::Replayed)
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.seen.values().map(HashSet::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{Encoding, MockClock, RollingTokenManager, TokenContext, ValidationError};
+
+    #[test]
+    fn test_one_time_use() {
+        let clock = MockClock::from_unix_secs(3000);
+        let mut manager = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .one_time_use(true)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        let token = manager.generate_token().unwrap();
+        assert!(manager.is_valid(token.as_str()));
+        assert_eq!(manager.validate(token.as_str()), Err(ValidationError::Replayed));
+        assert_eq!(manager.validate(&token.as_str().to_uppercase()), Err(ValidationError::Replayed));
+
+        // A nonce in the context makes every token unique
+        let first = manager
+            .generate_token_with_context(&TokenContext::new().with_field("nonce", "1"))
+            .unwrap();
+        let second = manager
+            .generate_token_with_context(&TokenContext::new().with_field("nonce", "2"))
+            .unwrap();
+        assert!(manager.is_valid_with_context(first.as_str(), &TokenContext::new().with_field("nonce", "1")));
+        assert!(manager.is_valid_with_context(second.as_str(), &TokenContext::new().with_field("nonce", "2")));
+        assert_eq!(manager.replay_cache.as_ref().unwrap().lock().unwrap().len(), 3);
+
+        clock.advance(Duration::from_secs(60));
+        let next = manager.generate_token().unwrap();
+        assert!(manager.is_valid(next.as_str()));
+        assert_eq!(manager.replay_cache.as_ref().unwrap().lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_clones_share_replay_cache() {
+        let mut a = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .one_time_use(true)
+            .clock(MockClock::from_unix_secs(3000))
+            .build()
+            .unwrap();
+        let mut b = a.clone();
+
+        let token = a.generate_token().unwrap();
+        assert!(a.is_valid(token.as_str()));
+        assert_eq!(b.validate(token.as_str()), Err(ValidationError::Replayed));
+    }
+
+    #[test]
+    fn test_one_time_use_versioned() {
+        let mut manager = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .encoding(Encoding::Versioned)
+            .one_time_use(true)
+            .clock(MockClock::from_unix_secs(3000))
+            .build()
+            .unwrap();
+
+        let token = manager.generate_token().unwrap();
+        assert!(manager.is_valid(token.as_str()));
+        assert_eq!(manager.validate(token.as_str()), Err(ValidationError::Replayed));
+        let padded = token.as_str().replace(".100.", ".0100.");
+        assert_eq!(manager.validate(&padded), Err(ValidationError::Malformed));
+    }
+}
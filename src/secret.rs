@@ -0,0 +1,76 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::algorithm::KeyedMac;
+
+/// Key material, deliberately not `Debug`.
+///
+/// Clones share one copy. With the `zeroize` feature, the bytes are wiped when the last clone is dropped.
+#[derive(Clone)]
+pub(crate) struct Secret(Arc<SecretBytes>);
+
+struct SecretBytes(Vec<u8>);
+
+impl Secret {
+    pub(crate) fn new(bytes: Vec<u8>) -> Self {
+        Self(Arc::new(SecretBytes(bytes)))
+    }
+
+    pub(crate) fn expose(&self) -> &[u8] {
+        &self.0 .0
+    }
+}
+
+/// Wipes a temporary copy of key material with the `zeroize` feature.
+#[cfg(feature = "zeroize")]
+pub(crate) fn wipe(bytes: &mut Vec<u8>) {
+    bytes.zeroize();
+}
+
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn wipe(_bytes: &mut Vec<u8>) {}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Keyed MAC states of a keyring, indexed like its keys. They are as sensitive as the secrets, so clones share
+/// them rather than copying them.
+///
+/// Unlike a [`Secret`], they are not wiped by the `zeroize` feature: the hash crates don't support zeroizing their
+/// states, and every signature works on a copy of the state anyway.
+#[derive(Clone)]
+pub(crate) struct KeyedMacs(Arc<[KeyedMac]>);
+
+impl KeyedMacs {
+    pub(crate) fn new(macs: Vec<KeyedMac>) -> Self {
+        Self(macs.into())
+    }
+}
+
+impl Deref for KeyedMacs {
+    type Target = [KeyedMac];
+
+    fn deref(&self) -> &[KeyedMac] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_secret() {
+        let secret = Secret::new(b"secret".to_vec());
+        let clone = secret.clone();
+        assert_eq!(clone.expose(), b"secret");
+        assert!(std::ptr::eq(secret.expose(), clone.expose()));
+    }
+}
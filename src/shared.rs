@@ -0,0 +1,203 @@
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use arc_swap::ArcSwap;
+
+use crate::replay::ReplayCache;
+use crate::{ActiveToken, GenerateError, Presented, RollingTokenManager, Token, TokenBytes, TokenContext, ValidatedToken, ValidationError};
+
+/// Expected codes for one interval, swapped in as a whole when the interval or the set of live keys changes.
+struct Window {
+    current_time: i64,
+    live_keys: Vec<bool>,
+    active_tokens: Vec<ActiveToken>,
+}
+
+/// A `Send + Sync` validator that can be shared behind an [`Arc`] and validates through `&self`.
+///
+/// Validations read an atomically swapped snapshot of the expected codes, so they don't contend on a lock. When
+/// the interval rolls over, the first validation to notice builds the next snapshot; concurrent ones may build it
+/// too, which is harmless. In one-time-use mode, accepted tokens are recorded under a mutex.
+///
+/// Created with [`RollingTokenManager::into_shared`].
+pub struct SharedValidator {
+    manager: RollingTokenManager,
+    window: ArcSwap<Window>,
+    replay_cache: Option<Arc<Mutex<ReplayCache>>>,
+}
+
+impl SharedValidator {
+    pub fn generate_token(&self) -> Result<Token, GenerateError> {
+        self.manager.generate_token()
+    }
+
+    pub fn generate_token_with_offset(&self, offset: i64) -> Result<Token, GenerateError> {
+        self.manager.generate_token_with_offset(offset)
+    }
+
+    pub fn generate_token_with_context(&self, context: &TokenContext) -> Result<Token, GenerateError> {
+        self.manager.generate_token_with_context(context)
+    }
+
+    pub fn generate_token_with_context_and_offset(&self, context: &TokenContext, offset: i64) -> Result<Token, GenerateError> {
+        self.manager.generate_token_with_context_and_offset(context, offset)
+    }
+
+    pub fn generate_token_bytes(&self) -> Result<TokenBytes, GenerateError> {
+        self.manager.generate_token_bytes()
+    }
+
+    pub fn generate_token_bytes_with_offset(&self, offset: i64) -> Result<TokenBytes, GenerateError> {
+        self.manager.generate_token_bytes_with_offset(offset)
+    }
+
+    /// See [`RollingTokenManager::validate`].
+    pub fn validate(&self, token: &str) -> Result<ValidatedToken, ValidationError> {
+        self.validate_with_context(token, &TokenContext::new())
+    }
+
+    /// See [`RollingTokenManager::validate_with_context`].
+    pub fn validate_with_context(&self, token: &str, context: &TokenContext) -> Result<ValidatedToken, ValidationError> {
+        self.validate_presented(self.manager.present(token), context)
+    }
+
+    /// See [`RollingTokenManager::validate_bytes`].
+    pub fn validate_bytes(&self, token: &TokenBytes) -> Result<ValidatedToken, ValidationError> {
+        self.validate_presented(self.manager.present_bytes(token), &TokenContext::new())
+    }
+
+    fn validate_presented(&self, presented: Presented<'_>, context: &TokenContext) -> Result<ValidatedToken, ValidationError> {
+        let manager = &self.manager;
+        let now = manager.clock.now();
+        let current_time = manager.timestamp_at(now)?;
+
+        let (validated, code) = match presented {
+            Presented::Versioned(token) => manager.validate_versioned(token, context, now, current_time)?,
+            Presented::Code(parsed) => {
+                let window = self.window(now, current_time);
+                manager.validate_code(&window.active_tokens, parsed, context, now, current_time)?
+            }
+        };
+
+        if let Some(replay_cache) = &self.replay_cache {
+            replay_cache.lock().unwrap().check(&validated, code, manager.past_tolerance)?;
+        }
+
+        Ok(validated)
+    }
+
+    pub fn is_valid(&self, token: &str) -> bool {
+        self.validate(token).is_ok()
+    }
+
+    pub fn is_valid_with_context(&self, token: &str, context: &TokenContext) -> bool {
+        self.validate_with_context(token, context).is_ok()
+    }
+
+    /// Shares `previous`'s replay cache, so tokens accepted before a swap stay rejected after it.
+    pub(crate) fn share_replay_cache(mut self, previous: &SharedValidator) -> Self {
+        if self.replay_cache.is_some() {
+            self.replay_cache = previous.replay_cache.clone();
+        }
+        self
+    }
+
+    fn window(&self, now: SystemTime, current_time: i64) -> Arc<Window> {
+        let keys = self.manager.keyring.keys();
+        let window = self.window.load_full();
+        let keys_unchanged = keys.iter().zip(&window.live_keys).all(|(key, &live)| key.is_live(now) == live);
+        if window.current_time == current_time && keys_unchanged {
+            return window;
+        }
+
+        let mut active_tokens = window.active_tokens.clone();
+        self.manager.refresh_tokens(&mut active_tokens, now, current_time);
+        let fresh = Arc::new(Window {
+            current_time,
+            live_keys: keys.iter().map(|key| key.is_live(now)).collect(),
+            active_tokens,
+        });
+        self.window.store(fresh.clone());
+        fresh
+    }
+}
+
+impl RollingTokenManager {
+    /// Converts the manager into a validator that can be shared between threads.
+    pub fn into_shared(mut self) -> SharedValidator {
+        let window = Window {
+            current_time: i64::MIN,
+            live_keys: Vec::new(),
+            active_tokens: std::mem::take(&mut self.active_tokens),
+        };
+        let replay_cache = self.replay_cache.take();
+
+        SharedValidator {
+            manager: self,
+            window: ArcSwap::from_pointee(window),
+            replay_cache,
+        }
+    }
+}
+
+impl From<RollingTokenManager> for SharedValidator {
+    fn from(manager: RollingTokenManager) -> Self {
+        manager.into_shared()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::MockClock;
+
+    #[test]
+    fn test_concurrent_validation() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SharedValidator>();
+
+        let clock = MockClock::from_unix_secs(3000);
+        let validator = Arc::new(
+            RollingTokenManager::builder("test_secret")
+                .interval_secs(30)
+                .clock(clock.clone())
+                .build()
+                .unwrap()
+                .into_shared(),
+        );
+
+        for _ in 0..3 {
+            let token = validator.generate_token().unwrap();
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let validator = Arc::clone(&validator);
+                    let token = token.to_string();
+                    thread::spawn(move || (0..100).all(|_| validator.is_valid(&token)))
+                })
+                .collect();
+            assert!(handles.into_iter().all(|handle| handle.join().unwrap()));
+
+            clock.advance(Duration::from_secs(30));
+            assert!(validator.is_valid(token.as_str()));
+            clock.advance(Duration::from_secs(30));
+            assert_eq!(validator.validate(token.as_str()), Err(ValidationError::Expired { offset: -2 }));
+        }
+    }
+
+    #[test]
+    fn test_shared_one_time_use() {
+        let validator = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .one_time_use(true)
+            .build()
+            .unwrap()
+            .into_shared();
+
+        let token = validator.generate_token().unwrap();
+        assert!(validator.is_valid(token.as_str()));
+        assert_eq!(validator.validate(token.as_str()), Err(ValidationError::Replayed));
+    }
+}
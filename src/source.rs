@@ -0,0 +1,252 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use data_encoding::{BASE32_NOPAD, BASE64_NOPAD};
+
+use crate::secret;
+use crate::{RollingTokenManager, RollingTokenManagerBuilder};
+
+/// How the bytes of a [`SecretSource`] are encoded.
+///
+/// With the `serde` feature, encodings are written in lowercase, e.g. `"base64"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum SecretEncoding {
+    /// The bytes are the secret.
+    #[default]
+    Raw,
+    /// Hexadecimal, in either case.
+    Hex,
+    /// Standard base64, with or without padding.
+    Base64,
+    /// RFC 4648 base32 as used by authenticator apps, in either case and with or without spaces or padding.
+    Base32,
+}
+
+impl SecretEncoding {
+    pub(crate) fn decode(self, encoded: &[u8]) -> Option<Vec<u8>> {
+        // Encoded secrets are often copied with surrounding whitespace
+        match self {
+            Self::Raw => Some(encoded.to_vec()),
+            Self::Hex => hex::decode(encoded.trim_ascii()).ok(),
+            Self::Base64 => {
+                let encoded = encoded.trim_ascii();
+                let unpadded = encoded.strip_suffix(b"==").or(encoded.strip_suffix(b"=")).unwrap_or(encoded);
+                BASE64_NOPAD.decode(unpadded).ok()
+            }
+            Self::Base32 => {
+                let mut normalized: Vec<u8> = encoded
+                    .iter()
+                    .filter(|c| !c.is_ascii_whitespace() && **c != b'=')
+                    .map(u8::to_ascii_uppercase)
+                    .collect();
+                let decoded = BASE32_NOPAD.decode(&normalized).ok();
+                secret::wipe(&mut normalized);
+                decoded
+            }
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Hex => "hex",
+            Self::Base64 => "base64",
+            Self::Base32 => "base32",
+        }
+    }
+}
+
+/// Where a secret comes from in a deployment: an environment variable, a file or a literal value.
+///
+/// Trailing newlines, as left by `echo` or editors, are trimmed. Encoded secrets are also trimmed of other
+/// surrounding whitespace, so binary secrets should be stored encoded rather than raw.
+///
+/// With the `serde` feature, sources are written as `{ "env": "TOKEN_SECRET", "encoding": "hex" }`,
+/// `{ "file": "/run/secrets/token" }` or `{ "value": "..." }`, with the encoding defaulting to raw.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SecretSource {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    location: Location,
+    #[cfg_attr(feature = "serde", serde(default))]
+    encoding: SecretEncoding,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+enum Location {
+    Env(String),
+    File(PathBuf),
+    Value(String),
+}
+
+impl SecretSource {
+    /// Reads the secret from the environment variable `name`.
+    pub fn env(name: impl Into<String>) -> Self {
+        Self::at(Location::Env(name.into()))
+    }
+
+    /// Reads the secret from the file at `path`, e.g. a mounted secret.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::at(Location::File(path.into()))
+    }
+
+    /// Uses `value` itself, e.g. a hex secret from a command-line flag.
+    pub fn literal(value: impl Into<String>) -> Self {
+        Self::at(Location::Value(value.into()))
+    }
+
+    fn at(location: Location) -> Self {
+        Self {
+            location,
+            encoding: SecretEncoding::Raw,
+        }
+    }
+
+    /// Sets how the secret is encoded (defaults to [`SecretEncoding::Raw`]).
+    pub fn encoding(mut self, encoding: SecretEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Reads and decodes the secret.
+    pub fn load(&self) -> Result<Vec<u8>, SecretError> {
+        let mut encoded = match &self.location {
+            Location::Env(name) => std::env::var_os(name)
+                .ok_or_else(|| SecretError::MissingEnv(name.clone()))?
+                .into_encoded_bytes(),
+            Location::File(path) => std::fs::read(path).map_err(|err| SecretError::UnreadableFile {
+                path: path.clone(),
+                kind: err.kind(),
+            })?,
+            Location::Value(value) => value.as_bytes().to_vec(),
+        };
+
+        let trimmed = encoded.len() - encoded.iter().rev().take_while(|&&b| b == b'\n' || b == b'\r').count();
+        let decoded = self.encoding.decode(&encoded[..trimmed]);
+        secret::wipe(&mut encoded);
+
+        let secret = decoded.ok_or_else(|| SecretError::InvalidEncoding {
+            origin: self.origin(),
+            encoding: self.encoding,
+        })?;
+        if secret.is_empty() {
+            return Err(SecretError::Empty(self.origin()));
+        }
+        Ok(secret)
+    }
+
+    /// Describes where the secret comes from, without revealing literal values.
+    fn origin(&self) -> String {
+        match &self.location {
+            Location::Env(name) => format!("environment variable {name}"),
+            Location::File(path) => format!("file {}", path.display()),
+            Location::Value(_) => "literal value".to_string(),
+        }
+    }
+}
+
+/// Redacts literal values.
+impl fmt::Debug for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretSource")
+            .field("origin", &self.origin())
+            .field("encoding", &self.encoding)
+            .finish()
+    }
+}
+
+impl RollingTokenManager {
+    /// Creates a builder for a manager with a single secret loaded from `source`.
+    pub fn builder_from_source(source: &SecretSource) -> Result<RollingTokenManagerBuilder, SecretError> {
+        Ok(Self::builder(source.load()?))
+    }
+}
+
+/// A [`SecretSource`] could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretError {
+    /// The environment variable is not set.
+    MissingEnv(String),
+    /// The file could not be read.
+    UnreadableFile { path: PathBuf, kind: io::ErrorKind },
+    /// The secret from this origin is not valid in the configured encoding.
+    InvalidEncoding { origin: String, encoding: SecretEncoding },
+    /// The secret from this origin is empty.
+    Empty(String),
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingEnv(name) => write!(f, "environment variable {name} is not set"),
+            Self::UnreadableFile { path, kind } => write!(f, "cannot read secret file {}: {kind}", path.display()),
+            Self::InvalidEncoding { origin, encoding } => write!(f, "secret from {origin} is not valid {}", encoding.name()),
+            Self::Empty(origin) => write!(f, "secret from {origin} is empty"),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn test_encodings() {
+        let load = |value: &str, encoding| SecretSource::literal(value).encoding(encoding).load();
+
+        assert_eq!(load("secret\n", SecretEncoding::Raw), Ok(b"secret".to_vec()));
+        assert_eq!(load(" secret \r\n", SecretEncoding::Raw), Ok(b" secret ".to_vec()));
+        assert_eq!(load("DEADbeef\n", SecretEncoding::Hex), Ok(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(load("c2VjcmV0", SecretEncoding::Base64), Ok(b"secret".to_vec()));
+        assert_eq!(load("c2VjcmV0MQ==\n", SecretEncoding::Base64), Ok(b"secret1".to_vec()));
+        assert_eq!(
+            load("jbsw y3dp ehpk 3pxp", SecretEncoding::Base32),
+            Ok(b"Hello!\xde\xad\xbe\xef".to_vec())
+        );
+
+        assert_eq!(
+            load("c2VjcmV0!", SecretEncoding::Base64),
+            Err(SecretError::InvalidEncoding {
+                origin: "literal value".to_string(),
+                encoding: SecretEncoding::Base64,
+            })
+        );
+        assert_eq!(
+            load("\n", SecretEncoding::Raw),
+            Err(SecretError::Empty("literal value".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_env_and_file() {
+        let missing = SecretSource::env("ROLLING_TOKEN_AUTH_UNSET").load();
+        assert_eq!(missing, Err(SecretError::MissingEnv("ROLLING_TOKEN_AUTH_UNSET".to_string())));
+
+        let path = TempPath::file("source", "74657374\n");
+        let source = SecretSource::file(&*path).encoding(SecretEncoding::Hex);
+        assert_eq!(source.load(), Ok(b"test".to_vec()));
+        std::fs::remove_file(&path).unwrap();
+
+        let err = source.load().unwrap_err();
+        assert_eq!(
+            err,
+            SecretError::UnreadableFile {
+                path: path.to_path_buf(),
+                kind: io::ErrorKind::NotFound
+            }
+        );
+        assert!(err.to_string().starts_with("cannot read secret file "));
+    }
+
+    #[test]
+    fn test_debug_redacts_literals() {
+        let debug = format!("{:?}", SecretSource::literal("hunter2"));
+        assert!(!debug.contains("hunter2"));
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::Mutex;
+
+use crate::replay::ReplayCache;
+use crate::{GenerateError, RollingTokenManager, Token, TokenContext, ValidatedToken, ValidationError};
+
+/// Validates tokens of many users or devices, each with its own secret derived from a master secret, without
+/// storing a table of secrets.
+///
+/// Every subject ID derives its own manager with [`RollingTokenManager::for_subject`], so a device only ever holds
+/// its derived secret, e.g. enrolled through [`RollingTokenManager::otpauth_uri`], and compromising it reveals
+/// nothing about the master secret or other devices. Validation derives the subject's keys on every call, so it
+/// costs one HKDF and the MACs of the tolerance window per key. It is `Send + Sync` and validates through `&self`.
+///
+/// Created with [`RollingTokenManager::into_subject_validator`]. In one-time-use mode, accepted tokens are recorded
+/// per subject under a mutex.
+pub struct SubjectValidator {
+    master: RollingTokenManager,
+    replay_cache: Option<Mutex<ReplayCache>>,
+}
+
+impl SubjectValidator {
+    /// Returns the manager of `subject_id`, e.g. to provision its device.
+    pub fn manager(&self, subject_id: &str) -> RollingTokenManager {
+        self.master.for_subject(subject_id)
+    }
+
+    pub fn generate_token(&self, subject_id: &str) -> Result<Token, GenerateError> {
+        self.manager(subject_id).generate_token()
+    }
+
+    pub fn generate_token_with_context(&self, subject_id: &str, context: &TokenContext) -> Result<Token, GenerateError> {
+        self.manager(subject_id).generate_token_with_context(context)
+    }
+
+    /// Validates `token` against the keys of `subject_id`, see [`RollingTokenManager::validate`].
+    pub fn validate(&self, subject_id: &str, token: &str) -> Result<ValidatedToken, ValidationError> {
+        self.validate_with_context(subject_id, token, &TokenContext::new())
+    }
+
+    /// See [`RollingTokenManager::validate_with_context`].
+    pub fn validate_with_context(&self, subject_id: &str, token: &str, context: &TokenContext) -> Result<ValidatedToken, ValidationError> {
+        let mut manager = self.manager(subject_id);
+        let (validated, code) = manager.check_presented(manager.present(token), context)?;
+
+        if let Some(replay_cache) = &self.replay_cache {
+            // Short codes of different subjects can collide, so the cache tells them apart by subject
+            let scoped = ValidatedToken {
+                key_id: format!("{}:{subject_id}{}", subject_id.len(), validated.key_id).into(),
+                ..validated.clone()
+            };
+            replay_cache.lock().unwrap().check(&scoped, code, manager.past_tolerance)?;
+        }
+
+        Ok(validated)
+    }
+
+    pub fn is_valid(&self, subject_id: &str, token: &str) -> bool {
+        self.validate(subject_id, token).is_ok()
+    }
+
+    pub fn is_valid_with_context(&self, subject_id: &str, token: &str, context: &TokenContext) -> bool {
+        self.validate_with_context(subject_id, token, context).is_ok()
+    }
+}
+
+impl RollingTokenManager {
+    /// Converts the manager into a validator of per-subject tokens derived from its keys.
+    ///
+    /// The manager's own tokens are not accepted by the validator.
+    pub fn into_subject_validator(mut self) -> SubjectValidator {
+        let replay_cache = self.replay_cache.take().map(|_| Mutex::new(ReplayCache::default()));
+        self.active_tokens = Vec::new();
+        SubjectValidator {
+            master: self,
+            replay_cache,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Encoding, MockClock};
+
+    fn validator(one_time_use: bool) -> SubjectValidator {
+        RollingTokenManager::builder("master_secret")
+            .interval_secs(30)
+            .encoding(Encoding::Totp { digits: 6 })
+            .one_time_use(one_time_use)
+            .clock(MockClock::from_unix_secs(3000))
+            .build()
+            .unwrap()
+            .into_subject_validator()
+    }
+
+    #[test]
+    fn test_subjects_are_separated() {
+        let validator = validator(false);
+        let alice = validator.manager("alice");
+        let token = alice.generate_token().unwrap();
+
+        assert!(validator.is_valid("alice", token.as_str()));
+        assert!(!validator.is_valid("bob", token.as_str()));
+        assert_eq!(validator.generate_token("alice").unwrap(), token);
+
+        // Subject and purpose derivations are distinct
+        let master = RollingTokenManager::builder("master_secret")
+            .interval_secs(30)
+            .encoding(Encoding::Totp { digits: 6 })
+            .clock(MockClock::from_unix_secs(3000))
+            .build()
+            .unwrap();
+        assert!(!master.derive("alice").is_valid(token.as_str()));
+        assert!(!master.clone().is_valid(token.as_str()));
+    }
+
+    #[test]
+    fn test_one_time_use_per_subject() {
+        let validator = validator(true);
+        let token = validator.generate_token("alice").unwrap();
+        assert!(validator.is_valid("alice", token.as_str()));
+        assert_eq!(validator.validate("alice", token.as_str()), Err(ValidationError::Replayed));
+
+        let bob = validator.generate_token("bob").unwrap();
+        assert!(validator.is_valid("bob", bob.as_str()));
+    }
+
+    #[test]
+    fn test_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        assert_send_sync(&validator(false));
+    }
+}
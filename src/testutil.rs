@@ -0,0 +1,44 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A unique path in the temporary directory, removed with whatever was created there when dropped, so failing
+/// tests don't leave secrets behind.
+pub(crate) struct TempPath(PathBuf);
+
+impl TempPath {
+    pub(crate) fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("rolling-token-auth-{}-{name}", std::process::id())))
+    }
+
+    /// Creates the path as a file with `contents`.
+    pub(crate) fn file(name: &str, contents: &str) -> Self {
+        let path = Self::new(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // Tests may have removed it already
+        let _ = if self.0.is_dir() {
+            std::fs::remove_dir_all(&self.0)
+        } else {
+            std::fs::remove_file(&self.0)
+        };
+    }
+}
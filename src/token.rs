@@ -0,0 +1,329 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use subtle::ConstantTimeEq;
+
+use crate::{Clock, ValidationError};
+
+/// A generated token, with the times the generating manager accepts it.
+///
+/// Tokens parsed with [`FromStr`] carry no timing metadata, so the interval and validity accessors return `None`.
+/// `Display` renders the token itself.
+///
+/// With the `serde` feature, tokens serialize with their metadata. Deserialized tokens compute
+/// [`remaining`](Self::remaining) against the system clock.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "TokenRepr", from = "TokenRepr")
+)]
+pub struct Token {
+    token: String,
+    interval_index: Option<i64>,
+    validity: Option<Validity>,
+}
+
+/// When a manager accepts a token, given its interval, the tolerance window and the epoch.
+#[derive(Clone)]
+pub(crate) struct Validity {
+    pub(crate) valid_from: SystemTime,
+    pub(crate) expires_at: SystemTime,
+    pub(crate) clock: Arc<dyn Clock>,
+}
+
+impl Token {
+    /// `validity` is `None` if the tolerance window can't be represented as a `SystemTime`.
+    pub(crate) fn generated(token: String, interval_index: i64, validity: Option<Validity>) -> Self {
+        Self {
+            token,
+            interval_index: Some(interval_index),
+            validity,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.token
+    }
+
+    pub fn into_string(self) -> String {
+        self.token
+    }
+
+    /// The interval the token was generated for, counted from the manager's epoch.
+    pub fn interval_index(&self) -> Option<i64> {
+        self.interval_index
+    }
+
+    /// The earliest time the manager accepts the token, i.e. the start of its interval minus the future tolerance.
+    pub fn valid_from(&self) -> Option<SystemTime> {
+        self.validity.as_ref().map(|validity| validity.valid_from)
+    }
+
+    /// The time the manager stops accepting the token, i.e. the end of its interval plus the past tolerance.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.validity.as_ref().map(|validity| validity.expires_at)
+    }
+
+    /// How long the token remains valid according to the manager's clock, or zero if it has expired.
+    pub fn remaining(&self) -> Option<Duration> {
+        let validity = self.validity.as_ref()?;
+        Some(validity.expires_at.duration_since(validity.clock.now()).unwrap_or(Duration::ZERO))
+    }
+}
+
+/// Serialized form of a [`Token`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TokenRepr {
+    token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interval_index: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    valid_from: Option<SystemTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<SystemTime>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Token> for TokenRepr {
+    fn from(token: Token) -> Self {
+        Self {
+            valid_from: token.valid_from(),
+            expires_at: token.expires_at(),
+            interval_index: token.interval_index,
+            token: token.token,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<TokenRepr> for Token {
+    fn from(repr: TokenRepr) -> Self {
+        let validity = match (repr.valid_from, repr.expires_at) {
+            (Some(valid_from), Some(expires_at)) => Some(Validity {
+                valid_from,
+                expires_at,
+                clock: Arc::new(crate::SystemClock),
+            }),
+            _ => None,
+        };
+        Self {
+            token: repr.token,
+            interval_index: repr.interval_index,
+            validity,
+        }
+    }
+}
+
+impl AsRef<str> for Token {
+    fn as_ref(&self) -> &str {
+        &self.token
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.token)
+    }
+}
+
+/// Wraps a presented token. Only empty tokens are rejected here; everything else is left to validation.
+impl FromStr for Token {
+    type Err = ValidationError;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        if token.is_empty() {
+            return Err(ValidationError::Malformed);
+        }
+        Ok(Self {
+            token: token.to_string(),
+            interval_index: None,
+            validity: None,
+        })
+    }
+}
+
+/// Redacts the token, which is a credential.
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Token")
+            .field("interval_index", &self.interval_index)
+            .field("valid_from", &self.valid_from())
+            .field("expires_at", &self.expires_at())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Compares the tokens in constant time with respect to their contents. Timing metadata is ignored.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.token.as_bytes().ct_eq(other.token.as_bytes()).into()
+    }
+}
+
+impl Eq for Token {}
+
+/// Length of [`TokenBytes`], the output length of HMAC-SHA256.
+pub const TOKEN_BYTES_LEN: usize = 32;
+
+/// A hex token in its raw form, for hot paths and binary protocols that shouldn't allocate a `String` per token.
+///
+/// Only managers with [`Encoding::Hex`](crate::Encoding::Hex) and a 32-byte MAC (the default HMAC-SHA256, or
+/// SHA3-256 and BLAKE3) produce and accept token bytes. `Display` renders the same hex as
+/// [`Token::as_str`].
+#[derive(Clone, Copy)]
+pub struct TokenBytes(pub [u8; TOKEN_BYTES_LEN]);
+
+impl TokenBytes {
+    pub fn as_bytes(&self) -> &[u8; TOKEN_BYTES_LEN] {
+        &self.0
+    }
+}
+
+impl From<[u8; TOKEN_BYTES_LEN]> for TokenBytes {
+    fn from(bytes: [u8; TOKEN_BYTES_LEN]) -> Self {
+        Self(bytes)
+    }
+}
+
+/// Parses a 64-character hex token.
+impl FromStr for TokenBytes {
+    type Err = ValidationError;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0; TOKEN_BYTES_LEN];
+        hex::decode_to_slice(token, &mut bytes).map_err(|_| ValidationError::Malformed)?;
+        Ok(Self(bytes))
+    }
+}
+
+impl fmt::Display for TokenBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+/// Redacts the token, which is a credential.
+impl fmt::Debug for TokenBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenBytes(..)")
+    }
+}
+
+/// Compares in constant time.
+impl PartialEq for TokenBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.ct_eq(&other.0).into()
+    }
+}
+
+impl Eq for TokenBytes {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Encoding, GenerateError, MockClock, RollingTokenManager};
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_token_validity() {
+        let clock = MockClock::from_unix_secs(3010);
+        let manager = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .past_tolerance(2)
+            .future_tolerance(1)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let at = |secs| Some(UNIX_EPOCH + Duration::from_secs(secs));
+
+        let token = manager.generate_token().unwrap();
+        assert_eq!(token.interval_index(), Some(100));
+        assert_eq!(token.valid_from(), at(2970));
+        assert_eq!(token.expires_at(), at(3090));
+        assert_eq!(token.remaining(), Some(Duration::from_secs(80)));
+
+        let next = manager.generate_token_with_offset(1).unwrap();
+        assert_eq!((next.interval_index(), next.valid_from()), (Some(101), at(3000)));
+
+        clock.advance(Duration::from_secs(100));
+        assert_eq!(token.remaining(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_token_string_conversions() {
+        let manager = RollingTokenManager::new("test_secret", 30, None);
+        let token = manager.generate_token().unwrap();
+
+        let parsed: Token = token.to_string().parse().unwrap();
+        assert_eq!(parsed, token);
+        assert_eq!((parsed.interval_index(), parsed.remaining()), (None, None));
+        assert_ne!(parsed, "00".parse().unwrap());
+        assert_eq!("".parse::<Token>(), Err(ValidationError::Malformed));
+
+        let debug = format!("{token:?}");
+        assert!(debug.starts_with("Token { interval_index: Some("));
+        assert!(!debug.contains(token.as_str()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_token_serde() {
+        let manager = RollingTokenManager::new("test_secret", 30, None).with_clock(MockClock::from_unix_secs(3000));
+        let token = manager.generate_token().unwrap();
+
+        let json = serde_json::to_string(&token).unwrap();
+        assert!(json.starts_with(&format!(r#"{{"token":"{token}","interval_index":100,"valid_from":"#)));
+        let restored: Token = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, token);
+        assert_eq!(
+            (restored.interval_index(), restored.valid_from(), restored.expires_at()),
+            (token.interval_index(), token.valid_from(), token.expires_at())
+        );
+
+        let bare: Token = serde_json::from_str(&format!(r#"{{"token":"{token}"}}"#)).unwrap();
+        assert_eq!((bare.interval_index(), bare.remaining()), (None, None));
+    }
+
+    #[test]
+    fn test_token_bytes() {
+        let mut manager = RollingTokenManager::new("test_secret", 30, None).with_clock(MockClock::from_unix_secs(3000));
+
+        let bytes = manager.generate_token_bytes_with_offset(-1).unwrap();
+        let token = manager.generate_token_with_offset(-1).unwrap();
+        assert_eq!(bytes.to_string(), token.as_str());
+        assert_eq!(token.as_str().parse(), Ok(bytes));
+        assert_eq!(format!("{bytes:?}"), "TokenBytes(..)");
+
+        assert_eq!(manager.validate_bytes(&bytes).map(|validated| validated.offset), Ok(-1));
+        assert_eq!(
+            manager.validate_bytes(&TokenBytes([0; TOKEN_BYTES_LEN])),
+            Err(ValidationError::Unknown)
+        );
+        assert_eq!("00".parse::<TokenBytes>(), Err(ValidationError::Malformed));
+    }
+
+    #[test]
+    fn test_unsupported_managers() {
+        let mut totp = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .encoding(Encoding::Totp { digits: 6 })
+            .build()
+            .unwrap();
+        assert_eq!(totp.generate_token_bytes(), Err(GenerateError::UnsupportedTokenBytes));
+        assert_eq!(
+            totp.validate_bytes(&TokenBytes([0; TOKEN_BYTES_LEN])),
+            Err(ValidationError::Malformed)
+        );
+
+        let sha512 = RollingTokenManager::builder("test_secret")
+            .interval_secs(30)
+            .algorithm(crate::Algorithm::Sha512)
+            .build()
+            .unwrap();
+        assert_eq!(sha512.generate_token_bytes(), Err(GenerateError::UnsupportedTokenBytes));
+    }
+}
@@ -0,0 +1,57 @@
+//! Checks that validation doesn't allocate once the expected codes are cached.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use rolling_token_auth::{MockClock, RollingTokenManager, TokenBytes, ValidationError};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[test]
+fn test_validation_does_not_allocate() {
+    let mut manager = RollingTokenManager::new("test_secret", 30, None).with_clock(MockClock::from_unix_secs(3000));
+    let token = manager.generate_token_with_offset(-1).unwrap();
+    let bytes: TokenBytes = token.as_str().parse().unwrap();
+    let expired = manager.generate_token_bytes_with_offset(-3).unwrap();
+    assert!(manager.is_valid(token.as_str())); // fills the cache
+
+    assert_eq!(allocations(|| manager.generate_token_bytes().is_ok()), (true, 0));
+    assert_eq!(allocations(|| manager.is_valid(token.as_str())), (true, 0));
+    assert_eq!(allocations(|| manager.validate_bytes(&bytes).is_ok()), (true, 0));
+    assert_eq!(
+        allocations(|| manager.validate_bytes(&expired)),
+        (Err(ValidationError::Expired { offset: -3 }), 0)
+    );
+    assert_eq!(
+        allocations(|| manager.validate("invalid_token")),
+        (Err(ValidationError::Malformed), 0)
+    );
+
+    let validator = manager.into_shared();
+    assert!(validator.is_valid(token.as_str()));
+    assert_eq!(allocations(|| validator.validate_bytes(&bytes).is_ok()), (true, 0));
+}